@@ -0,0 +1,262 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+use crate::{AStarResult, Edge, Graph};
+
+#[derive(Debug, Clone, PartialEq)]
+struct SearchNode {
+    id: usize,
+    key: f64,
+}
+
+impl Eq for SearchNode {}
+
+impl Ord for SearchNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.partial_cmp(&other.key).unwrap()
+    }
+}
+
+impl PartialOrd for SearchNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// the state of one half of a bidirectional search
+struct SearchHalf {
+    q: BinaryHeap<Reverse<SearchNode>>,
+    distances: Vec<Option<f64>>,
+    parents: Vec<Option<usize>>,
+    settled: Vec<bool>,
+}
+
+impl SearchHalf {
+    fn new(nr_nodes: usize, start_idx: usize, key: f64) -> Self {
+        let mut half = SearchHalf {
+            q: BinaryHeap::new(),
+            distances: vec![None; nr_nodes],
+            parents: vec![None; nr_nodes],
+            settled: vec![false; nr_nodes],
+        };
+        half.distances[start_idx] = Some(0.0);
+        half.q.push(Reverse(SearchNode { id: start_idx, key }));
+        half
+    }
+
+    fn top_key(&self) -> f64 {
+        self.q.peek().map_or(f64::INFINITY, |node| node.0.key)
+    }
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
+    /// calculates the shortest path between two nodes with a bidirectional A* search, returns the path and the distance
+    ///
+    /// the forward half follows the edges from `start`, the backward half follows the reverse adjacency from `end`.
+    /// Both halves use the average potential `(h(v, end) - h(start, v)) / 2`, which is consistent if the heuristic is.
+    /// If landmarks are available, the stronger of the heuristic and the landmark bound is taken on both sides.
+    /// A constant heuristic without landmarks turns this into a bidirectional Dijkstra search.
+    ///
+    /// only the static edge weights are used, time-dependent weight lists are ignored
+    pub fn bidirectional_astar(
+        &self,
+        start: &T,
+        end: &T,
+        heuristic: impl Fn(&T, &T) -> f64,
+    ) -> anyhow::Result<AStarResult<T>> {
+        let node_map_access = self.node_map.as_ref().read().unwrap();
+        let node_list_access = self.nodes.as_ref().read().unwrap();
+        let edges_access = self.edges.as_ref().read().unwrap();
+        let reverse_edges_access = self.reverse_edges.as_ref().read().unwrap();
+        let nr_nodes = node_list_access.len();
+
+        let start_idx = *node_map_access
+            .get_by_left(start)
+            .ok_or_else(|| anyhow::anyhow!("start node {start:?} not found in node map"))?;
+        let end_idx = *node_map_access
+            .get_by_left(end)
+            .ok_or_else(|| anyhow::anyhow!("end node {end:?} not found in node map"))?;
+
+        let landmarks_access = self.landmarks.as_ref().read().unwrap();
+        let landmarks = landmarks_access.as_ref();
+        let potential = |idx: usize| {
+            node_list_access[idx].as_ref().map_or(0.0, |node| {
+                let (to_end, from_start) = match landmarks {
+                    Some(table) => (
+                        heuristic(&node.id, end).max(table.lower_bound(idx, end_idx)),
+                        heuristic(start, &node.id).max(table.lower_bound(start_idx, idx)),
+                    ),
+                    None => (heuristic(&node.id, end), heuristic(start, &node.id)),
+                };
+                (to_end - from_start) / 2.0
+            })
+        };
+        let weight = |from: usize, to: usize| {
+            edges_access
+                .get(&from)
+                .and_then(|edges| edges.get(&Edge::new(from, to, None, None)))
+                .map_or(f64::INFINITY, |edge| edge.weight.unwrap_or(1.0))
+        };
+
+        let mut forward = SearchHalf::new(nr_nodes, start_idx, potential(start_idx));
+        let mut backward = SearchHalf::new(nr_nodes, end_idx, -potential(end_idx));
+
+        // the length of the best path found so far and the node where both halves met
+        let mut best: Option<(f64, usize)> = if start_idx == end_idx {
+            Some((0.0, start_idx))
+        } else {
+            None
+        };
+
+        loop {
+            let best_distance = best.map_or(f64::INFINITY, |(distance, _)| distance);
+            if forward.q.is_empty()
+                || backward.q.is_empty()
+                || forward.top_key() + backward.top_key() >= best_distance
+            {
+                break;
+            }
+
+            let is_forward = forward.top_key() <= backward.top_key();
+            let (current, other) = if is_forward {
+                (&mut forward, &backward)
+            } else {
+                (&mut backward, &forward)
+            };
+
+            let current_idx = current.q.pop().unwrap().0.id;
+            if current.settled[current_idx] {
+                continue;
+            }
+            current.settled[current_idx] = true;
+            let current_distance = current.distances[current_idx]
+                .ok_or(anyhow::anyhow!("current distance was not recorded"))?;
+
+            let neighbors: Vec<(usize, f64)> = if is_forward {
+                edges_access.get(&current_idx).map_or(vec![], |edges| {
+                    edges
+                        .iter()
+                        .map(|edge| (edge.to, edge.weight.unwrap_or(1.0)))
+                        .collect()
+                })
+            } else {
+                reverse_edges_access
+                    .get(&current_idx)
+                    .map_or(vec![], |sources| {
+                        sources
+                            .iter()
+                            .map(|from| (*from, weight(*from, current_idx)))
+                            .collect()
+                    })
+            };
+
+            for (next_idx, edge_weight) in neighbors {
+                let tentative_distance = current_distance + edge_weight;
+                if tentative_distance < current.distances[next_idx].unwrap_or(f64::INFINITY) {
+                    current.distances[next_idx] = Some(tentative_distance);
+                    current.parents[next_idx] = Some(current_idx);
+                    let key = if is_forward {
+                        tentative_distance + potential(next_idx)
+                    } else {
+                        tentative_distance - potential(next_idx)
+                    };
+                    current.q.push(Reverse(SearchNode { id: next_idx, key }));
+                }
+                if let (Some(next_distance), Some(other_distance)) =
+                    (current.distances[next_idx], other.distances[next_idx])
+                {
+                    let total = next_distance + other_distance;
+                    if total < best.map_or(f64::INFINITY, |(distance, _)| distance) {
+                        best = Some((total, next_idx));
+                    }
+                }
+            }
+        }
+
+        let Some((distance, meeting_idx)) = best else {
+            return Err(anyhow::anyhow!("no path found"));
+        };
+
+        // forward parents lead back to the start, backward parents lead on to the end
+        let mut path_idx = vec![meeting_idx];
+        while let Some(parent) = forward.parents[*path_idx.last().unwrap()] {
+            path_idx.push(parent);
+        }
+        path_idx.reverse();
+        while let Some(next) = backward.parents[*path_idx.last().unwrap()] {
+            path_idx.push(next);
+        }
+
+        Ok(AStarResult {
+            path: Some(
+                path_idx
+                    .into_iter()
+                    .filter_map(|idx| node_list_access[idx].as_ref().map(|node| node.id))
+                    .collect(),
+            ),
+            single_target: true,
+            distances: vec![Some(distance)],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hexagon_graph::h3cell::H3Cell;
+    use crate::landmarks::LandmarkStrategy;
+    use crate::test_graph::{astar_distance, cell, disk_network, graph};
+    use crate::Graph;
+
+    /// checks the distance and the end points of the path for all pairs of the 19 cells
+    fn assert_matches_astar(graph: &Graph<H3Cell>, heuristic: impl Fn(&H3Cell, &H3Cell) -> f64) {
+        for start in (0..19).map(|idx| cell(idx, -1)) {
+            for end in (0..19).map(|idx| cell(idx, -1)) {
+                let res = graph.bidirectional_astar(&start, &end, &heuristic).unwrap();
+                let expected = astar_distance(graph, start, end).unwrap();
+                assert!((res.distances[0].unwrap() - expected).abs() < 1e-9);
+                let path = res.path.unwrap();
+                assert_eq!((path.first(), path.last()), (Some(&start), Some(&end)));
+            }
+        }
+    }
+
+    #[test]
+    fn matches_astar_without_heuristic() {
+        assert_matches_astar(&disk_network(), |_, _| 0.0);
+    }
+
+    #[test]
+    fn matches_astar_with_a_geographic_heuristic() {
+        let graph = disk_network();
+        let max_speed = graph.max_edge_speed();
+        assert_matches_astar(&graph, |from, to| from.distance_m(to) / max_speed);
+    }
+
+    #[test]
+    fn matches_astar_with_landmarks() {
+        let graph = disk_network();
+        graph
+            .build_landmarks(4, LandmarkStrategy::Farthest)
+            .unwrap();
+        assert_matches_astar(&graph, |_, _| 0.0);
+    }
+
+    #[test]
+    fn does_not_stop_at_the_first_meeting_node() {
+        // 1 is halfway from both ends, but the shorter path over 2 and 3 only meets on the edge between them
+        let walk = |idx| cell(idx, -1);
+        let graph = graph(&[
+            (walk(0), walk(1), 3.0),
+            (walk(1), walk(4), 3.0),
+            (walk(0), walk(2), 2.0),
+            (walk(2), walk(3), 1.5),
+            (walk(3), walk(4), 2.0),
+        ]);
+        let res = graph
+            .bidirectional_astar(&walk(0), &walk(4), |_, _| 0.0)
+            .unwrap();
+        assert_eq!(res.distances[0], Some(5.5));
+        assert_eq!(res.path.unwrap(), vec![walk(0), walk(2), walk(3), walk(4)]);
+    }
+}
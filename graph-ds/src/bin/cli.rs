@@ -34,6 +34,7 @@ fn main() -> anyhow::Result<()> {
     //     &WeightModifier::default(),
     //     "resources/denver/denver_gtfs.zip",
     //     0,
+    //     h3o::Resolution::Twelve,
     // )
     // .unwrap();
     // // let (mut gtfs_graph_2, _) = h3_network_from_gtfs("resources/gtfs_bus.zip", offset).unwrap();
@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use crate::Graph;
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
+    /// assigns every node index to a weakly connected component, edge directions are ignored
+    ///
+    /// the components are numbered by size, component 0 is the largest one. Removed nodes have no component.
+    pub fn weakly_connected_components(&self) -> Vec<Option<usize>> {
+        let nodes = self.nodes.as_ref().read().unwrap();
+        let edges = self.edges.as_ref().read().unwrap();
+        let reverse_edges = self.reverse_edges.as_ref().read().unwrap();
+
+        let mut components: Vec<Option<usize>> = vec![None; nodes.len()];
+        let mut sizes: Vec<usize> = Vec::new();
+        let mut q: VecDeque<usize> = VecDeque::new();
+
+        for start_idx in 0..nodes.len() {
+            if components[start_idx].is_some() || nodes[start_idx].is_none() {
+                continue;
+            }
+            let component = sizes.len();
+            let mut size = 0;
+            components[start_idx] = Some(component);
+            q.push_back(start_idx);
+
+            while let Some(current_idx) = q.pop_front() {
+                size += 1;
+                let successors = edges
+                    .get(&current_idx)
+                    .into_iter()
+                    .flatten()
+                    .map(|edge| edge.to);
+                let predecessors = reverse_edges
+                    .get(&current_idx)
+                    .into_iter()
+                    .flatten()
+                    .cloned();
+                for next_idx in successors.chain(predecessors) {
+                    if components[next_idx].is_none() {
+                        components[next_idx] = Some(component);
+                        q.push_back(next_idx);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+
+        // renumber the components by descending size
+        let mut order = (0..sizes.len()).collect::<Vec<_>>();
+        order.sort_by_key(|component| std::cmp::Reverse(sizes[*component]));
+        let mut rank = vec![0; sizes.len()];
+        for (new_component, old_component) in order.into_iter().enumerate() {
+            rank[old_component] = new_component;
+        }
+        components
+            .into_iter()
+            .map(|component| component.map(|c| rank[c]))
+            .collect()
+    }
+
+    /// returns the nodes of the largest weakly connected component
+    pub fn largest_component(&self) -> Vec<T> {
+        let components = self.weakly_connected_components();
+        let nodes = self.nodes.as_ref().read().unwrap();
+        components
+            .into_iter()
+            .zip(nodes.iter())
+            .filter_map(|(component, node)| match (component, node) {
+                (Some(0), Some(node)) => Some(node.id),
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,505 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+
+use rayon::prelude::*;
+
+use crate::{AStarResult, Graph, Layered};
+
+/// limits for a constrained search, `None` disables the respective limit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoutingConstraints {
+    /// maximum number of transit boardings (edges from the base layer into a route layer)
+    pub max_transfers: Option<u32>,
+    /// maximum total time spent on base layer edges
+    pub max_walk_time: Option<f64>,
+    /// maximum total distance covered on base layer edges, in the unit of the `walk_distance` function
+    pub max_walk_distance: Option<f64>,
+}
+
+impl RoutingConstraints {
+    pub fn is_unconstrained(&self) -> bool {
+        self.max_transfers.is_none()
+            && self.max_walk_time.is_none()
+            && self.max_walk_distance.is_none()
+    }
+}
+
+/// the type of an edge, derived from the layers of its end nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// both nodes are on the base layer
+    Walk,
+    /// from the base layer into a transit route layer
+    Boarding,
+    Other,
+}
+
+impl EdgeKind {
+    pub fn between<T: Layered>(from: &T, to: &T) -> Self {
+        match (from.layer(), to.layer()) {
+            (-1, -1) => EdgeKind::Walk,
+            (-1, l) if l >= 0 => EdgeKind::Boarding,
+            _ => EdgeKind::Other,
+        }
+    }
+}
+
+/// the state of a partial journey that ends at `node`
+///
+/// labels are stored in an arena, `parent` is the index of the previous label of the journey
+#[derive(Debug, Clone, Copy)]
+pub struct Label {
+    pub node: usize,
+    pub time: f64,
+    pub transfers: u32,
+    pub walk_time: f64,
+    pub walk_distance: f64,
+    pub parent: Option<usize>,
+}
+
+impl Label {
+    /// checks the label against all limits of the constraints
+    pub fn is_feasible(&self, constraints: &RoutingConstraints) -> bool {
+        self.transfers <= constraints.max_transfers.unwrap_or(u32::MAX)
+            && self.walk_time <= constraints.max_walk_time.unwrap_or(f64::INFINITY)
+            && self.walk_distance <= constraints.max_walk_distance.unwrap_or(f64::INFINITY)
+    }
+
+    /// a label dominates another if it is at least as good in the travel time and in every compared criterion
+    pub fn dominates(&self, other: &Label, criteria: &Criteria) -> bool {
+        self.time <= other.time
+            && (!criteria.transfers || self.transfers <= other.transfers)
+            && (!criteria.walk_time || self.walk_time <= other.walk_time)
+            && (!criteria.walk_distance || self.walk_distance <= other.walk_distance)
+    }
+
+    /// the label reached by following an edge of the given kind from this label
+    pub fn extend(
+        &self,
+        parent: usize,
+        to: usize,
+        weight: f64,
+        kind: EdgeKind,
+        walk_distance: f64,
+    ) -> Label {
+        let mut next = Label {
+            node: to,
+            time: self.time + weight,
+            parent: Some(parent),
+            ..*self
+        };
+        match kind {
+            EdgeKind::Walk => {
+                next.walk_time += weight;
+                next.walk_distance += walk_distance;
+            }
+            EdgeKind::Boarding => next.transfers += 1,
+            EdgeKind::Other => {}
+        }
+        next
+    }
+}
+
+/// the criteria besides the travel time that are compared when checking labels for dominance
+#[derive(Debug, Clone, Copy)]
+pub struct Criteria {
+    pub transfers: bool,
+    pub walk_time: bool,
+    pub walk_distance: bool,
+}
+
+impl From<&RoutingConstraints> for Criteria {
+    /// only the constrained criteria need to be compared
+    fn from(constraints: &RoutingConstraints) -> Self {
+        Criteria {
+            transfers: constraints.max_transfers.is_some(),
+            walk_time: constraints.max_walk_time.is_some(),
+            walk_distance: constraints.max_walk_distance.is_some(),
+        }
+    }
+}
+
+/// the arena of all labels created during a search, with the non-dominated labels of every node
+pub(crate) struct LabelBags {
+    pub labels: Vec<Label>,
+    dead: Vec<bool>,
+    bags: Vec<Vec<usize>>,
+}
+
+impl LabelBags {
+    pub fn new(nr_nodes: usize) -> Self {
+        Self {
+            labels: Vec::new(),
+            dead: Vec::new(),
+            bags: vec![Vec::new(); nr_nodes],
+        }
+    }
+
+    /// a label is dead once it has been dominated by a label at the same node
+    pub fn is_dead(&self, label: usize) -> bool {
+        self.dead[label]
+    }
+
+    /// adds the label to the bag of its node, unless it is dominated by a label in the bag.
+    /// Labels that are dominated by the new label are removed. Returns the index of the new label.
+    pub fn insert(&mut self, label: Label, criteria: &Criteria) -> Option<usize> {
+        let labels = &self.labels;
+        if self.bags[label.node]
+            .iter()
+            .any(|idx| labels[*idx].dominates(&label, criteria))
+        {
+            return None;
+        }
+
+        let dead = &mut self.dead;
+        self.bags[label.node].retain(|idx| {
+            if label.dominates(&labels[*idx], criteria) {
+                dead[*idx] = true;
+                false
+            } else {
+                true
+            }
+        });
+
+        let label_idx = self.labels.len();
+        self.bags[label.node].push(label_idx);
+        self.labels.push(label);
+        self.dead.push(false);
+        Some(label_idx)
+    }
+
+    /// the node indices of the journey that ends with the given label
+    pub fn backtrace(&self, label: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut label_idx = Some(label);
+        while let Some(idx) = label_idx {
+            path.push(self.labels[idx].node);
+            label_idx = self.labels[idx].parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LabelEntry {
+    pub label: usize,
+    pub f_score: f64,
+}
+
+impl Eq for LabelEntry {}
+
+impl Ord for LabelEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.f_score.partial_cmp(&other.f_score).unwrap()
+    }
+}
+
+impl PartialOrd for LabelEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug + Layered> Graph<T> {
+    /// calculates the distances from a set of origins under routing constraints
+    ///
+    /// this function is parallelized using rayon
+    #[allow(clippy::too_many_arguments)]
+    pub fn matrix_constrained_distance(
+        &self,
+        origins: &Vec<T>,
+        destinations: &Vec<T>,
+        force: bool,
+        weight_list_index: Option<usize>,
+        infinity: Option<f64>,
+        dynamic_infinity: Option<bool>,
+        constraints: &RoutingConstraints,
+        walk_distance: impl Fn(&T, &T) -> f64 + Send + Sync + Copy,
+    ) -> HashMap<T, anyhow::Result<Vec<Option<f64>>>> {
+        let map_func = |s: &T| {
+            (
+                *s,
+                self.constrained_astar(
+                    s,
+                    None,
+                    Some(destinations),
+                    infinity,
+                    dynamic_infinity,
+                    weight_list_index,
+                    constraints,
+                    |_: &T, _: &T| 0.0,
+                    walk_distance,
+                )
+                .map(|res| res.distances),
+            )
+        };
+        if force {
+            origins.into_par_iter().map(map_func).collect()
+        } else {
+            // removes duplicates before iteration
+            origins
+                .iter()
+                .collect::<HashSet<&T>>()
+                .into_par_iter()
+                .map(map_func)
+                .collect()
+        }
+    }
+
+    /// label-setting A* search that respects a maximum number of transfers and a maximum walking time or distance
+    ///
+    /// every node keeps a set of non-dominated labels, so a slower journey with fewer transfers or less walking
+    /// survives as long as it can still lead to a feasible path.
+    /// * `walk_distance` returns the distance covered by a base layer edge
+    /// * for an `end_list`, the heuristic is not used and the distances are returned in the order of the list
+    /// * landmark bounds are used next to the heuristic if available
+    /// * with `dynamic_infinity`, the cutoff is lowered to the travel time of the first target that is reached
+    #[allow(clippy::too_many_arguments)]
+    pub fn constrained_astar(
+        &self,
+        start: &T,
+        end: Option<&T>,
+        end_list: Option<&Vec<T>>,
+        infinity: Option<f64>,
+        dynamic_infinity: Option<bool>,
+        weight_list_index: Option<usize>,
+        constraints: &RoutingConstraints,
+        heuristic: impl Fn(&T, &T) -> f64,
+        walk_distance: impl Fn(&T, &T) -> f64,
+    ) -> anyhow::Result<AStarResult<T>> {
+        let node_map_access = self.node_map.as_ref().read().unwrap();
+        let node_list_access = self.nodes.as_ref().read().unwrap();
+        let edges_access = self.edges.as_ref().read().unwrap();
+        let nr_nodes = node_list_access.len();
+
+        let start_idx = *node_map_access
+            .get_by_left(start)
+            .ok_or_else(|| anyhow::anyhow!("start node {start:?} not found in node map"))?;
+
+        let target_list = if let Some(end_list) = end_list {
+            if end_list.is_empty() {
+                return Err(anyhow::anyhow!("no end node provided"));
+            }
+            end_list.clone()
+        } else if let Some(end) = end {
+            vec![*end]
+        } else {
+            return Err(anyhow::anyhow!("no end node provided"));
+        };
+        let is_single_target = end.is_some();
+
+        let target_idx_list = target_list
+            .iter()
+            .map(|end| node_map_access.get_by_left(end).copied())
+            .collect::<Vec<_>>();
+        let known_target_idx_list = target_idx_list
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut target_idx_set = known_target_idx_list
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let mut target_distances: HashMap<usize, f64> = HashMap::new();
+
+        let mut infinity = infinity.unwrap_or(f64::INFINITY);
+        let node_id = |idx: usize| node_list_access[idx].as_ref().map(|node| node.id);
+        let landmarks_access = self.landmarks.as_ref().read().unwrap();
+        let landmarks = landmarks_access.as_ref();
+        let target_bounds = landmarks.map(|table| table.target_bounds(&known_target_idx_list));
+        let h = |idx: usize| {
+            let estimate = if is_single_target {
+                node_id(idx).map_or(0.0, |id| heuristic(&id, &target_list[0]))
+            } else {
+                0.0
+            };
+            match (landmarks, &target_bounds) {
+                (Some(table), Some(bounds)) if !known_target_idx_list.is_empty() => {
+                    estimate.max(table.lower_bound_to_targets(idx, bounds))
+                }
+                _ => estimate,
+            }
+        };
+
+        let criteria = Criteria::from(constraints);
+        let mut bags = LabelBags::new(nr_nodes);
+        let mut q: BinaryHeap<Reverse<LabelEntry>> = BinaryHeap::new();
+
+        let start_label = Label {
+            node: start_idx,
+            time: 0.0,
+            transfers: 0,
+            walk_time: 0.0,
+            walk_distance: 0.0,
+            parent: None,
+        };
+        if let Some(label) = bags.insert(start_label, &criteria) {
+            q.push(Reverse(LabelEntry {
+                label,
+                f_score: h(start_idx),
+            }));
+        }
+
+        while let Some(Reverse(entry)) = q.pop() {
+            if bags.is_dead(entry.label) {
+                continue;
+            }
+            let current = bags.labels[entry.label];
+            if current.time > infinity {
+                continue;
+            }
+
+            if target_idx_set.remove(&current.node) {
+                if dynamic_infinity.unwrap_or(false) {
+                    infinity = current.time;
+                }
+                target_distances.insert(current.node, current.time);
+                if is_single_target {
+                    let path = bags
+                        .backtrace(entry.label)
+                        .into_iter()
+                        .filter_map(node_id)
+                        .collect();
+                    return Ok(AStarResult {
+                        path: Some(path),
+                        single_target: is_single_target,
+                        distances: vec![Some(current.time)],
+                    });
+                } else if target_idx_set.is_empty() {
+                    break;
+                }
+            }
+
+            let Some(next_edges) = edges_access.get(&current.node) else {
+                continue;
+            };
+            let Some(current_id) = node_id(current.node) else {
+                continue;
+            };
+            for next_edge in next_edges.iter() {
+                let Some(next_id) = node_id(next_edge.to) else {
+                    continue;
+                };
+                let weight = next_edge.weight_at(weight_list_index);
+                let kind = EdgeKind::between(&current_id, &next_id);
+                let distance = if kind == EdgeKind::Walk && criteria.walk_distance {
+                    walk_distance(&current_id, &next_id)
+                } else {
+                    0.0
+                };
+
+                let next = current.extend(entry.label, next_edge.to, weight, kind, distance);
+                if next.time > infinity || !next.is_feasible(constraints) {
+                    continue;
+                }
+                if let Some(label) = bags.insert(next, &criteria) {
+                    q.push(Reverse(LabelEntry {
+                        label,
+                        f_score: next.time + h(next.node),
+                    }));
+                }
+            }
+        }
+
+        if end_list.is_some() {
+            Ok(AStarResult {
+                path: None,
+                single_target: is_single_target,
+                distances: target_idx_list
+                    .into_iter()
+                    .map(|idx| idx.and_then(|idx| target_distances.get(&idx).copied()))
+                    .collect::<Vec<_>>(),
+            })
+        } else {
+            Err(anyhow::anyhow!("no path found"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hexagon_graph::h3cell::H3Cell;
+    use crate::test_graph::{astar_distance, cell, transit_network};
+
+    fn distance(graph: &Graph<H3Cell>, constraints: RoutingConstraints) -> Option<f64> {
+        graph
+            .constrained_astar(
+                &cell(0, -1),
+                Some(&cell(3, -1)),
+                None,
+                None,
+                None,
+                None,
+                &constraints,
+                |_, _| 0.0,
+                H3Cell::distance_m,
+            )
+            .ok()
+            .and_then(|res| res.distances[0])
+    }
+
+    #[test]
+    fn without_constraints_matches_astar() {
+        let graph = transit_network();
+        assert_eq!(distance(&graph, RoutingConstraints::default()), Some(6.0));
+        assert_eq!(astar_distance(&graph, cell(0, -1), cell(3, -1)), Some(6.0));
+    }
+
+    #[test]
+    fn counts_boardings_as_transfers() {
+        let graph = transit_network();
+        let max_transfers = |transfers| RoutingConstraints {
+            max_transfers: Some(transfers),
+            ..Default::default()
+        };
+        assert_eq!(distance(&graph, max_transfers(2)), Some(6.0));
+        assert_eq!(distance(&graph, max_transfers(1)), Some(13.0));
+        assert_eq!(distance(&graph, max_transfers(0)), Some(30.0));
+    }
+
+    #[test]
+    fn limits_the_walking_time() {
+        let graph = transit_network();
+        let constraints = RoutingConstraints {
+            max_walk_time: Some(10.0),
+            ..Default::default()
+        };
+        assert_eq!(distance(&graph, constraints), Some(6.0));
+        let constraints = RoutingConstraints {
+            max_transfers: Some(1),
+            max_walk_time: Some(5.0),
+            ..Default::default()
+        };
+        assert_eq!(distance(&graph, constraints), None);
+    }
+
+    #[test]
+    fn dynamic_infinity_stops_at_the_first_target() {
+        let graph = transit_network();
+        let constraints = RoutingConstraints {
+            max_transfers: Some(0),
+            ..Default::default()
+        };
+        let distances = |dynamic_infinity| {
+            graph.matrix_constrained_distance(
+                &vec![cell(0, -1)],
+                &vec![cell(1, -1), cell(3, -1)],
+                false,
+                None,
+                None,
+                Some(dynamic_infinity),
+                &constraints,
+                H3Cell::distance_m,
+            )[&cell(0, -1)]
+                .as_ref()
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(distances(false), vec![Some(10.0), Some(30.0)]);
+        assert_eq!(distances(true), vec![Some(10.0), None]);
+    }
+}
@@ -1,33 +1,86 @@
+pub mod aggregate;
+pub mod bike_and_ride;
 pub mod cell;
+pub mod coarsen;
+pub mod gbfs;
 pub mod gtfs;
 pub mod h3cell;
+pub mod heuristic;
+pub mod od_matrix;
 pub mod osm;
+pub mod pois;
+pub mod snapping;
+pub mod spatial_index;
+pub mod subgraph;
 
 use std::{
-    collections::{BTreeMap, HashMap},
-    sync::RwLockReadGuard,
+    collections::{BTreeMap, HashMap, HashSet},
     time::Instant,
 };
 
 use crate::{Edge, Graph};
-use bimap::BiHashMap;
 use cell::HexCell;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use self::{
     cell::Direction,
+    gtfs::WheelchairAccess,
     h3cell::H3Cell,
-    osm::{process_osm_pbf, OSMLayer},
+    heuristic::GeoHeuristic,
+    osm::{
+        elevation::ElevationOptions, process_osm_pbf, profile::OSMProfile, OSMBuildReport, OSMLayer,
+    },
+    snapping::SnapOptions,
+    spatial_index::SpatialIndex,
 };
 
+#[cfg(feature = "pyo3")]
+use crate::{constrained::RoutingConstraints, landmarks::LandmarkStrategy};
+#[cfg(feature = "pyo3")]
+use od_matrix::PyOdMatrix;
+#[cfg(feature = "pyo3")]
+use osm::elevation::SlopeModel;
+#[cfg(feature = "pyo3")]
+use pois::{process_pois, PoiCategories};
 #[cfg(feature = "pyo3")]
 use pyo3::types::PyDict;
+#[cfg(feature = "pyo3")]
+use snapping::SnapTarget;
+#[cfg(feature = "pyo3")]
+use spatial_index::BoundingBox;
+#[cfg(feature = "pyo3")]
+use subgraph::SubgraphFilter;
 
+#[derive(Clone)]
 pub struct OSMOptions {
     pub osm_layer: Option<OSMLayer>,
     pub gtfs_layer: bool,
     pub weight_modifier: WeightModifier,
+    /// the H3 resolution of all cells in the graph, the walking and cycling weights follow the cell size
+    pub resolution: h3o::Resolution,
+    /// tag rules and speed multipliers of the walking layer
+    pub walk_profile: OSMProfile,
+    /// tag rules and speed multipliers of the cycling layer
+    pub bike_profile: OSMProfile,
+    /// adds the driving layer next to the layers selected by `osm_layer`
+    pub driving_layer: bool,
+    /// tag rules, speeds and parking rules of the driving layer
+    pub drive_profile: OSMProfile,
+    /// collects bicycle parking to link the bike layer to the walking network near transit stops, see
+    /// `Graph::<H3Cell>::connect_bike_and_ride`. Not used with `gbfs_paths`
+    pub bike_and_ride: bool,
+    /// the maximum distance in meters between a bicycle parking and a stop
+    pub bike_and_ride_distance_m: f64,
+    /// GBFS `station_information.json` files, if given the bike layer is a bike-share layer that can only be
+    /// entered and left at docking stations
+    pub gbfs_paths: Vec<String>,
+    /// the maximum distance in meters between a docking station and the walking and bike networks
+    pub bike_share_distance_m: f64,
+    /// optional elevation raster, walking and cycling weights then depend on the slope and direction
+    pub elevation: Option<ElevationOptions>,
+    /// the wheelchair accessibility of the stops and trips in the transit layers
+    pub wheelchair_access: WheelchairAccess,
 }
 #[derive(Debug, Clone)]
 pub struct WeightModifier {
@@ -35,6 +88,14 @@ pub struct WeightModifier {
     pub wait_time_multiplier: f64,
     pub walk_speed: f64,
     pub bike_speed: f64,
+    /// minutes to park or get the car, between the driving and the walking layer
+    pub parking_penalty: f64,
+    /// minutes to lock the bike at a bicycle parking before boarding transit
+    pub bike_parking_penalty: f64,
+    /// minutes to pick up a shared bike at a docking station
+    pub pickup_penalty: f64,
+    /// minutes to return a shared bike at a docking station
+    pub dropoff_penalty: f64,
 }
 
 #[cfg(feature = "pyo3")]
@@ -53,12 +114,28 @@ impl FromPyObject<'_> for WeightModifier {
         let bike_speed = dict
             .get_item("bike_speed").and_then(|x| x.extract::<f64>().ok())
             .unwrap_or(4.5);
+        let parking_penalty = dict
+            .get_item("parking_penalty").and_then(|x| x.extract::<f64>().ok())
+            .unwrap_or(5.0);
+        let bike_parking_penalty = dict
+            .get_item("bike_parking_penalty").and_then(|x| x.extract::<f64>().ok())
+            .unwrap_or(2.0);
+        let pickup_penalty = dict
+            .get_item("pickup_penalty").and_then(|x| x.extract::<f64>().ok())
+            .unwrap_or(1.0);
+        let dropoff_penalty = dict
+            .get_item("dropoff_penalty").and_then(|x| x.extract::<f64>().ok())
+            .unwrap_or(1.0);
 
         Ok(WeightModifier {
             bike_penalty,
             wait_time_multiplier,
             walk_speed,
             bike_speed,
+            parking_penalty,
+            bike_parking_penalty,
+            pickup_penalty,
+            dropoff_penalty,
         })
     }
 }
@@ -69,10 +146,61 @@ impl Default for OSMOptions {
             osm_layer: None,
             gtfs_layer: true,
             weight_modifier: WeightModifier::default(),
+            resolution: h3o::Resolution::Twelve,
+            walk_profile: OSMProfile::walking(),
+            bike_profile: OSMProfile::cycling(),
+            driving_layer: false,
+            drive_profile: OSMProfile::driving(),
+            bike_and_ride: false,
+            bike_and_ride_distance_m: 150.0,
+            gbfs_paths: vec![],
+            bike_share_distance_m: 50.0,
+            elevation: None,
+            wheelchair_access: WheelchairAccess::Ignore,
         }
     }
 }
 
+impl OSMOptions {
+    /// the OSM layers to build, walking and cycling if `osm_layer` is not set
+    pub fn osm_layers(&self) -> Vec<OSMLayer> {
+        let mut layers = match self.osm_layer {
+            Some(layer) => vec![layer],
+            None => vec![OSMLayer::Cycling, OSMLayer::Walking],
+        };
+        if self.driving_layer && !layers.contains(&OSMLayer::Driving) {
+            layers.push(OSMLayer::Driving);
+        }
+        layers
+    }
+
+    /// a step-free network for wheelchair users, the walking layer uses `OSMProfile::wheelchair` and the transit
+    /// layers only keep accessible stops and trips
+    pub fn step_free(self, wheelchair_access: WheelchairAccess) -> Self {
+        OSMOptions {
+            walk_profile: OSMProfile::wheelchair(),
+            wheelchair_access,
+            ..self
+        }
+    }
+
+    /// bike-and-ride links are only created for owned bikes, shared bikes have to be returned at docking stations
+    pub fn bike_and_ride_enabled(&self) -> bool {
+        self.bike_and_ride && self.gbfs_paths.is_empty()
+    }
+
+    /// replaces the profile of a layer with one loaded from a TOML or JSON file
+    pub fn load_profile(&mut self, layer: OSMLayer, path: &str) -> anyhow::Result<()> {
+        let profile = OSMProfile::from_path(path)?;
+        match layer {
+            OSMLayer::Cycling => self.bike_profile = profile,
+            OSMLayer::Walking => self.walk_profile = profile,
+            OSMLayer::Driving => self.drive_profile = profile,
+        }
+        Ok(())
+    }
+}
+
 impl Default for WeightModifier {
     fn default() -> Self {
         WeightModifier {
@@ -80,6 +208,10 @@ impl Default for WeightModifier {
             wait_time_multiplier: 1.0,
             walk_speed: 1.4,
             bike_speed: 4.5,
+            parking_penalty: 5.0,
+            bike_parking_penalty: 2.0,
+            pickup_penalty: 1.0,
+            dropoff_penalty: 1.0,
         }
     }
 }
@@ -143,11 +275,34 @@ pub fn cell_graph_from_mpk(path: &str) -> anyhow::Result<Graph<HexCell>> {
 }
 
 pub fn h3_network_from_osm(osm_url: &str, options: &OSMOptions) -> anyhow::Result<Graph<H3Cell>> {
-    let edge_data = process_osm_pbf(osm_url, options, h3o::Resolution::Twelve)?;
+    h3_network_from_osm_with_report(osm_url, options).map(|(graph, _)| graph)
+}
 
-    let mut graph = Graph::<H3Cell>::new();
+/// like `h3_network_from_osm`, with statistics and the bicycle parking cells for bike-and-ride links
+pub fn h3_network_from_osm_with_report(
+    osm_url: &str,
+    options: &OSMOptions,
+) -> anyhow::Result<(Graph<H3Cell>, OSMBuildReport)> {
+    let osm_data = process_osm_pbf(osm_url, options)?;
 
-    for ((layer, from, to), weight) in edge_data {
+    let mut graph = Graph::<H3Cell>::new();
+    let mut report = OSMBuildReport {
+        bike_parking: osm_data.bike_parking,
+        path_failures: osm_data.path_failures,
+        blocked_edges: osm_data.blocked_edges,
+        delayed_edges: osm_data.delayed_edges,
+        ..Default::default()
+    };
+    let mut walking_cells = HashSet::new();
+    let mut driving_cells = HashSet::new();
+
+    for ((layer, from, to), weight) in osm_data.edges {
+        *report.edges.entry(layer).or_default() += 1;
+        match layer {
+            OSMLayer::Walking => walking_cells.extend([from, to]),
+            OSMLayer::Driving => driving_cells.extend([from, to]),
+            OSMLayer::Cycling => {}
+        }
         let from_cell = H3Cell {
             cell: from,
             layer: layer.get_id(),
@@ -156,12 +311,11 @@ pub fn h3_network_from_osm(osm_url: &str, options: &OSMOptions) -> anyhow::Resul
             cell: to,
             layer: layer.get_id(),
         };
-        // connect the two cells in both directions
+        // the edges are directed, one-way rules are already applied
         graph.build_and_add_egde(from_cell, to_cell, Some(weight), None, None)?;
-        graph.build_and_add_egde(to_cell, from_cell, Some(weight), None, None)?;
 
-        if layer == OSMLayer::Cycling {
-            // connect to the base layer
+        if layer == OSMLayer::Cycling && options.gbfs_paths.is_empty() {
+            // connect to the base layer, bike owners can switch anywhere
             let from_base_cell = H3Cell {
                 cell: from,
                 layer: -1,
@@ -200,16 +354,56 @@ pub fn h3_network_from_osm(osm_url: &str, options: &OSMOptions) -> anyhow::Resul
             )?;
         }
     }
-    Ok(graph)
+
+    // park the car or get back to it where parking is allowed and the walking network is reachable
+    for cell in osm_data.parking {
+        if !walking_cells.contains(&cell) || !driving_cells.contains(&cell) {
+            continue;
+        }
+        let base_cell = H3Cell {
+            cell,
+            layer: OSMLayer::Walking.get_id(),
+        };
+        let driving_cell = H3Cell {
+            cell,
+            layer: OSMLayer::Driving.get_id(),
+        };
+        let penalty = Some(options.weight_modifier.parking_penalty);
+        graph.build_and_add_egde(driving_cell, base_cell, penalty, None, None)?;
+        graph.build_and_add_egde(base_cell, driving_cell, penalty, None, None)?;
+        report.parking_links += 2;
+    }
+
+    // shared bikes can only be picked up and returned at docking stations
+    if !options.gbfs_paths.is_empty() {
+        let mut stations = Vec::new();
+        for path in &options.gbfs_paths {
+            stations.extend(gbfs::read_stations(path)?);
+        }
+        (report.bike_share_stations, report.invalid_stations) = graph.connect_bike_share(
+            &stations,
+            options.bike_share_distance_m,
+            &options.weight_modifier,
+        )?;
+        if report.invalid_stations > 0 {
+            println!(
+                "skipped {} docking stations with invalid coordinates",
+                report.invalid_stations
+            );
+        }
+    }
+    Ok((graph, report))
 }
 
 pub fn h3_network_from_gtfs(
     options: &WeightModifier,
     gtfs_url: &str,
     route_index_offset: usize,
+    resolution: h3o::Resolution,
+    wheelchair_access: WheelchairAccess,
 ) -> anyhow::Result<(Graph<H3Cell>, usize)> {
     let weight_time_multiplier = options.wait_time_multiplier;
-    let gtfs_res = gtfs::process_gtfs(gtfs_url, route_index_offset, h3o::Resolution::Twelve)?;
+    let gtfs_res = gtfs::process_gtfs(gtfs_url, route_index_offset, resolution, wheelchair_access)?;
     let nr_routes = gtfs_res.nr_routes;
     let weight_lists = gtfs_res.stop_frequencies;
     let mut graph = Graph::<H3Cell>::new();
@@ -322,35 +516,143 @@ impl Graph<H3Cell> {
 pub struct PyH3Graph {
     graph: Graph<H3Cell>,
     options: OSMOptions,
-    k_ring: u32,
+    snap_options: SnapOptions,
+    heuristic: GeoHeuristic,
+    spatial_index: SpatialIndex,
 }
 
 #[cfg(feature = "pyo3")]
 #[allow(unused_variables)]
 #[pymethods]
 impl PyH3Graph {
+    /// nodes are snapped within `snap_distance` meters. `k_ring` is only used without a `snap_distance`, the radius
+    /// then covers `k_ring` rings of cells, 2 by default
+    /// * `layers` joins `all`, `walk`, `bike`, `transit` and `drive` with `+`, the walking layer is always built
+    /// * `resolution` is the H3 resolution of the graph, 12 by default
     #[new]
-    pub fn new(weight_options: Option<WeightModifier>, k_ring: u32, layers: String) -> Self {
-        let (gtfs_layer, osm_layer) = match layers.as_str() {
-            "walk+bike" => (false, None),
-            "walk" => (false, Some(OSMLayer::Walking)),
-            "walk+transit" => (true, Some(OSMLayer::Walking)),
-            _ => (true, None),
-        };
-        Self {
+    pub fn new(
+        weight_options: Option<WeightModifier>,
+        k_ring: Option<u32>,
+        layers: String,
+        snap_distance: Option<f64>,
+        resolution: Option<u8>,
+    ) -> PyResult<Self> {
+        let resolution = h3o::Resolution::try_from(resolution.unwrap_or(12))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        let (mut bike_layer, mut gtfs_layer, mut driving_layer) = (false, false, false);
+        for layer in layers.split('+') {
+            match layer.trim() {
+                "all" => (bike_layer, gtfs_layer) = (true, true),
+                "walk" => {}
+                "bike" => bike_layer = true,
+                "transit" => gtfs_layer = true,
+                "drive" => driving_layer = true,
+                other => {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "unknown layer {other}, expected all, walk, bike, transit or drive"
+                    )))
+                }
+            }
+        }
+        // the walking layer is always built, `None` adds the cycling layer
+        let osm_layer = (!bike_layer).then_some(OSMLayer::Walking);
+        Ok(Self {
             graph: Graph::<H3Cell>::new(),
             options: OSMOptions {
                 osm_layer,
                 gtfs_layer,
                 weight_modifier: weight_options.unwrap_or_default(),
+                resolution,
+                driving_layer,
+                ..Default::default()
+            },
+            snap_options: SnapOptions {
+                max_distance_m: snap_distance.unwrap_or_else(|| {
+                    SnapOptions::from_k_ring(k_ring.unwrap_or(2), resolution).max_distance_m
+                }),
+                ..Default::default()
             },
-            k_ring,
+            heuristic: GeoHeuristic::default(),
+            spatial_index: SpatialIndex::default(),
+        })
+    }
+
+    /// loads a TOML or JSON profile for the "walk", "bike" or "drive" layer, call before `create`
+    pub fn load_profile(&mut self, layer: &str, path: &str) -> PyResult<()> {
+        let layer = match layer {
+            "walk" => OSMLayer::Walking,
+            "bike" => OSMLayer::Cycling,
+            "drive" => OSMLayer::Driving,
+            other => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "unknown layer {other}, expected walk, bike or drive"
+                )))
+            }
+        };
+        self.options
+            .load_profile(layer, path)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+    }
+
+    /// uses a GeoTIFF DEM for slope-aware weights, the slope models are "flat", "tobler" or "power".
+    /// Call before `create`
+    pub fn set_elevation(
+        &mut self,
+        dem_path: &str,
+        walk_model: Option<String>,
+        bike_model: Option<String>,
+    ) -> PyResult<()> {
+        let parse = |model: Option<String>, default: SlopeModel| {
+            model.map_or(Ok(default), |model| {
+                model
+                    .parse::<SlopeModel>()
+                    .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+            })
+        };
+        let defaults = ElevationOptions::new(dem_path);
+        self.options.elevation = Some(ElevationOptions {
+            walk_model: parse(walk_model, defaults.walk_model)?,
+            bike_model: parse(bike_model, defaults.bike_model)?,
+            ..defaults
+        });
+        Ok(())
+    }
+
+    /// links the bike layer to the walking network at bicycle parking within `max_distance_m` of a transit stop,
+    /// 150 m by default.
+    /// Has no effect together with `set_bike_share`. Call before `create`
+    pub fn set_bike_and_ride(&mut self, max_distance_m: Option<f64>) {
+        self.options.bike_and_ride = true;
+        if let Some(max_distance_m) = max_distance_m {
+            self.options.bike_and_ride_distance_m = max_distance_m;
+        }
+    }
+
+    /// turns the bike layer into a bike-share layer, bikes are picked up and returned at the docking stations of
+    /// the GBFS `station_information.json` files. Call before `create`
+    pub fn set_bike_share(&mut self, gbfs_paths: Vec<String>, max_distance_m: Option<f64>) {
+        self.options.gbfs_paths = gbfs_paths;
+        if let Some(max_distance_m) = max_distance_m {
+            self.options.bike_share_distance_m = max_distance_m;
         }
     }
 
+    /// builds a step-free network for wheelchair users with the wheelchair walk profile. Transit stops and trips
+    /// marked as not accessible are left out, with `strict` also those without accessibility information.
+    /// Call before `create`
+    pub fn set_step_free(&mut self, strict: Option<bool>) {
+        let wheelchair_access = if strict.unwrap_or(false) {
+            WheelchairAccess::Accessible
+        } else {
+            WheelchairAccess::NotExcluded
+        };
+        self.options = self.options.clone().step_free(wheelchair_access);
+    }
+
     pub fn create(&mut self, osm_path: &str, gtfs_paths: Vec<String>) -> PyResult<()> {
         let start = Instant::now();
-        let mut osm_graph = h3_network_from_osm(osm_path, &self.options).unwrap();
+        let (mut osm_graph, report) =
+            h3_network_from_osm_with_report(osm_path, &self.options).unwrap();
 
         println!(
             "osm graph created with {} nodes in {} s",
@@ -363,9 +665,14 @@ impl PyH3Graph {
             for gtfs_path in gtfs_paths {
                 let start = Instant::now();
 
-                let (mut gtfs_graph, next_offset) =
-                    h3_network_from_gtfs(&self.options.weight_modifier, gtfs_path.as_str(), offset)
-                        .unwrap();
+                let (mut gtfs_graph, next_offset) = h3_network_from_gtfs(
+                    &self.options.weight_modifier,
+                    gtfs_path.as_str(),
+                    offset,
+                    self.options.resolution,
+                    self.options.wheelchair_access,
+                )
+                .unwrap();
                 offset += next_offset + 1;
 
                 println!(
@@ -388,16 +695,266 @@ impl PyH3Graph {
                     ));
                 }
             }
+            if self.options.bike_and_ride_enabled() {
+                let links = osm_graph
+                    .connect_bike_and_ride(
+                        &report.bike_parking,
+                        self.options.bike_and_ride_distance_m,
+                        &self.options.weight_modifier,
+                    )
+                    .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))?;
+                println!("added {links} bike-and-ride links");
+            }
             self.graph = osm_graph;
         } else {
             self.graph = osm_graph;
         }
 
+        self.heuristic = GeoHeuristic::for_graph(&self.graph, &self.options.weight_modifier);
+        self.spatial_index = self.graph.spatial_index(-1);
         println!("hash: {}", self.graph.node_hash());
 
         Ok(())
     }
 
+    /// returns a new graph restricted to a GeoJSON polygon and / or H3 parent cells, and / or to the given layers
+    ///
+    /// layers are -1 for walking, -2 for cycling, -3 for driving and the route index for transit
+    pub fn subgraph(
+        &self,
+        geojson: Option<String>,
+        parent_cells: Option<Vec<u64>>,
+        layers: Option<Vec<i16>>,
+    ) -> PyResult<PyH3Graph> {
+        let mut filter = SubgraphFilter {
+            parent_cells: parent_cells
+                .unwrap_or_default()
+                .into_iter()
+                .map(h3o::CellIndex::try_from)
+                .collect::<Result<_, _>>()
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?,
+            layers: layers.map(|layers| layers.into_iter().collect()),
+            ..Default::default()
+        };
+        if let Some(geojson) = geojson {
+            filter = filter
+                .with_geojson(&geojson)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        }
+
+        let start = Instant::now();
+        let graph = self
+            .graph
+            .subgraph(&filter)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))?;
+        println!(
+            "subgraph created with {} nodes in {} ms",
+            graph.nr_nodes(),
+            start.elapsed().as_millis()
+        );
+
+        Ok(PyH3Graph {
+            heuristic: GeoHeuristic::for_graph(&graph, &self.options.weight_modifier),
+            spatial_index: graph.spatial_index(-1),
+            graph,
+            options: self.options.clone(),
+            snap_options: self.snap_options.clone(),
+        })
+    }
+
+    /// returns an approximate graph at a coarser H3 resolution, see `Graph::<H3Cell>::coarsen`
+    pub fn coarsen(&self, resolution: u8) -> PyResult<PyH3Graph> {
+        let resolution = h3o::Resolution::try_from(resolution)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        let start = Instant::now();
+        let graph = self
+            .graph
+            .coarsen(resolution)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))?;
+        println!(
+            "coarsened graph to resolution {resolution} with {} nodes in {} ms",
+            graph.nr_nodes(),
+            start.elapsed().as_millis()
+        );
+
+        Ok(PyH3Graph {
+            heuristic: GeoHeuristic::for_graph(&graph, &self.options.weight_modifier),
+            spatial_index: graph.spatial_index(-1),
+            graph,
+            options: OSMOptions {
+                resolution,
+                ..self.options.clone()
+            },
+            snap_options: self.snap_options.clone(),
+        })
+    }
+
+    /// reads the points of interest of an OSM pbf file into cells at the resolution of the graph, as a dict of
+    /// category to H3 cells. The categories are loaded from a TOML or JSON file, the built-in ones by default
+    pub fn pois(
+        &self,
+        osm_path: &str,
+        categories_path: Option<String>,
+    ) -> PyResult<HashMap<String, Vec<u64>>> {
+        let categories = categories_path
+            .map_or(Ok(PoiCategories::built_in()), PoiCategories::from_path)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        let pois = process_pois(osm_path, &categories, self.options.resolution)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))?;
+        Ok(pois
+            .into_iter()
+            .map(|(category, cells)| (category, cells.into_iter().map(u64::from).collect()))
+            .collect())
+    }
+
+    /// precomputes landmark distance tables for the ALT heuristic, `strategy` is either "farthest" or "avoid"
+    pub fn build_landmarks(&mut self, count: usize, strategy: Option<String>) -> PyResult<()> {
+        let strategy = match strategy.as_deref() {
+            Some("avoid") => LandmarkStrategy::Avoid,
+            _ => LandmarkStrategy::Farthest,
+        };
+        let start = Instant::now();
+        self.graph
+            .build_landmarks(count, strategy)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))?;
+        println!(
+            "built {count} landmarks in {} s",
+            start.elapsed().as_secs_f32()
+        );
+        Ok(())
+    }
+
+    /// writes the graph with its landmark tables to a brotli compressed MessagePack file
+    pub fn save_mpk(&self, mpk_path: &str) -> PyResult<()> {
+        self.graph
+            .to_mpk(mpk_path)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))
+    }
+
+    /// loads a graph written by `save_mpk`, including its landmark tables. The weight options are not stored,
+    /// the heuristic uses the options of this object
+    pub fn create_from_mpk(&mut self, mpk_path: &str) -> PyResult<()> {
+        self.graph = Graph::<H3Cell>::from_mpk(mpk_path)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyException, _>(e.to_string()))?;
+        self.heuristic = GeoHeuristic::for_graph(&self.graph, &self.options.weight_modifier);
+        self.spatial_index = self.graph.spatial_index(-1);
+        println!("hash: {}", self.graph.node_hash());
+        Ok(())
+    }
+
+    /// changes how ids and coordinates are snapped to the graph, omitted values are kept
+    pub fn set_snap_options(
+        &mut self,
+        max_distance_m: Option<f64>,
+        layer: Option<i16>,
+        largest_component_only: Option<bool>,
+    ) {
+        if let Some(max_distance_m) = max_distance_m {
+            self.snap_options.max_distance_m = max_distance_m;
+        }
+        if let Some(layer) = layer {
+            self.snap_options.layer = layer;
+        }
+        if let Some(largest_component_only) = largest_component_only {
+            self.snap_options.largest_component_only = largest_component_only;
+        }
+    }
+
+    /// snaps H3 cells at any resolution to the graph, returns (node cell, snap distance in meters) per cell
+    pub fn snap(&self, cells: Vec<u64>) -> Vec<Option<(u64, f64)>> {
+        self.graph
+            .snap_ids(&cells, &self.snap_options)
+            .into_iter()
+            .map(|snapped| {
+                snapped.map(|snapped| (u64::from(snapped.cell.cell), snapped.distance_m))
+            })
+            .collect()
+    }
+
+    /// snaps (lat, lng) coordinates to the graph, returns (node cell, snap distance in meters) per coordinate
+    pub fn snap_points(&self, points: Vec<(f64, f64)>) -> PyResult<Vec<Option<(u64, f64)>>> {
+        let targets = points
+            .into_iter()
+            .map(|(lat, lng)| h3o::LatLng::new(lat, lng).map(SnapTarget::Point))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        Ok(self
+            .graph
+            .snap(&targets, &self.snap_options)
+            .into_iter()
+            .map(|snapped| {
+                snapped.map(|snapped| (u64::from(snapped.cell.cell), snapped.distance_m))
+            })
+            .collect())
+    }
+
+    /// the base layer node closest to (lat, lng), returns the node cell and the distance in meters
+    ///
+    /// `max_distance_m` defaults to the snap distance of the graph
+    pub fn nearest_node(
+        &self,
+        lat: f64,
+        lng: f64,
+        max_distance_m: Option<f64>,
+    ) -> PyResult<Option<(u64, f64)>> {
+        let point = h3o::LatLng::new(lat, lng)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        Ok(self
+            .spatial_index
+            .nearest(
+                &point,
+                max_distance_m.unwrap_or(self.snap_options.max_distance_m),
+            )
+            .map(|(cell, distance)| (u64::from(cell.cell), distance)))
+    }
+
+    /// the base layer nodes whose center is inside the bounding box
+    pub fn nodes_in_bbox(
+        &self,
+        min_lat: f64,
+        min_lng: f64,
+        max_lat: f64,
+        max_lng: f64,
+    ) -> Vec<u64> {
+        let bbox = BoundingBox {
+            min_lat,
+            min_lng,
+            max_lat,
+            max_lng,
+        };
+        self.spatial_index
+            .in_bbox(&bbox)
+            .into_iter()
+            .map(|cell| u64::from(cell.cell))
+            .collect()
+    }
+
+    /// the base layer nodes whose center is inside the polygon, given as rings of (lat, lng) coordinates
+    pub fn nodes_in_polygon(
+        &self,
+        exterior: Vec<(f64, f64)>,
+        holes: Option<Vec<Vec<(f64, f64)>>>,
+    ) -> PyResult<Vec<u64>> {
+        let to_ring = |ring: Vec<(f64, f64)>| {
+            ring.into_iter()
+                .map(|(lat, lng)| h3o::LatLng::new(lat, lng))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+        };
+        let exterior = to_ring(exterior)?;
+        let holes = holes
+            .unwrap_or_default()
+            .into_iter()
+            .map(to_ring)
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(self
+            .spatial_index
+            .in_polygon(&exterior, &holes)
+            .into_iter()
+            .map(|cell| u64::from(cell.cell))
+            .collect())
+    }
+
     pub fn get_random_node(&self) -> PyResult<u64> {
         if let Some(cell) = self.graph.get_random_node() {
             Ok(cell.cell.into())
@@ -413,29 +970,39 @@ impl PyH3Graph {
         origin: u64,
         destination: u64,
         hour_of_week: Option<usize>,
+        max_transfers: Option<u32>,
+        max_walk_time: Option<f64>,
+        max_walk_distance: Option<f64>,
     ) -> PyResult<(Vec<u64>, f64)> {
         fn h(_start_cell: &H3Cell, _end_cell: &H3Cell) -> f64 {
             1.0
         }
 
-        let node_map_access = self.graph.node_map.as_ref().read().unwrap();
-        let node_mapping =
-            u64list_to_h3cells(&node_map_access, vec![origin, destination], self.k_ring);
-
-        node_mapping.iter().for_each(|(original, mapped)| {
-            if let Some(mapped) = mapped {
-                let mapped_u64 = u64::from(mapped.cell);
-                if original != &mapped_u64 {
-                    println!("nodes have been adjusted: {} -> {}", original, mapped_u64);
+        let node_mapping = self
+            .graph
+            .snap_ids(&[origin, destination], &self.snap_options);
+
+        [origin, destination]
+            .iter()
+            .zip(node_mapping.iter())
+            .for_each(|(original, mapped)| {
+                if let Some(mapped) = mapped {
+                    let mapped_u64 = u64::from(mapped.cell.cell);
+                    if original != &mapped_u64 {
+                        println!(
+                            "nodes have been adjusted: {} -> {} ({:.1} m)",
+                            original, mapped_u64, mapped.distance_m
+                        );
+                    }
                 }
-            }
-        });
+            });
 
-        let (Some(Some(origin)), Some(Some(destination))) = (node_mapping.get_by_left(&origin), node_mapping.get_by_left(&destination)) else {
+        let [Some(origin), Some(destination)] = node_mapping[..] else {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                 "origin or destination not found",
             ));
         };
+        let (origin, destination) = (&origin.cell, &destination.cell);
 
         println!(
             "astar from {} to {}",
@@ -443,9 +1010,30 @@ impl PyH3Graph {
             u64::from(destination.cell)
         );
 
-        let astar_res =
+        let constraints = RoutingConstraints {
+            max_transfers,
+            max_walk_time,
+            max_walk_distance,
+        };
+        let astar_res = if !constraints.is_unconstrained() {
+            self.graph.constrained_astar(
+                origin,
+                Some(destination),
+                None,
+                None,
+                None,
+                hour_of_week,
+                &constraints,
+                h,
+                H3Cell::distance_m,
+            )
+        } else if hour_of_week.is_none() {
+            // without time-dependent weights, the bidirectional search explores far fewer nodes
+            self.graph.bidirectional_astar(origin, destination, h)
+        } else {
             self.graph
-                .astar(origin, Some(destination), None, None, None, hour_of_week, h);
+                .astar(origin, Some(destination), None, None, None, hour_of_week, h)
+        };
 
         if let Ok(astar_res) = astar_res {
             if let (Some(path), Some(distance)) = (astar_res.path, astar_res.distances.first()) {
@@ -472,37 +1060,38 @@ impl PyH3Graph {
         origin: u64,
         destination: u64,
         hour_of_week: Option<usize>,
+        max_transfers: Option<u32>,
+        max_walk_time: Option<f64>,
+        max_walk_distance: Option<f64>,
     ) -> PyResult<(Vec<u64>, f64)> {
-        fn h(start_cell: &H3Cell, end_cell: &H3Cell) -> f64 {
-            if let Ok(dist) = start_cell.cell.grid_distance(end_cell.cell) {
-                dist as f64
-            } else {
-                println!(
-                    "grid distance failed between {} and {}",
-                    start_cell.cell, end_cell.cell
-                );
-                i32::MAX as f64
-            }
-        }
+        let h =
+            |start_cell: &H3Cell, end_cell: &H3Cell| self.heuristic.estimate(start_cell, end_cell);
 
-        let node_map_access = self.graph.node_map.as_ref().read().unwrap();
-        let node_mapping =
-            u64list_to_h3cells(&node_map_access, vec![origin, destination], self.k_ring);
-
-        node_mapping.iter().for_each(|(original, mapped)| {
-            if let Some(mapped) = mapped {
-                let mapped_u64 = u64::from(mapped.cell);
-                if original != &mapped_u64 {
-                    println!("nodes have been adjusted: {} -> {}", original, mapped_u64);
+        let node_mapping = self
+            .graph
+            .snap_ids(&[origin, destination], &self.snap_options);
+
+        [origin, destination]
+            .iter()
+            .zip(node_mapping.iter())
+            .for_each(|(original, mapped)| {
+                if let Some(mapped) = mapped {
+                    let mapped_u64 = u64::from(mapped.cell.cell);
+                    if original != &mapped_u64 {
+                        println!(
+                            "nodes have been adjusted: {} -> {} ({:.1} m)",
+                            original, mapped_u64, mapped.distance_m
+                        );
+                    }
                 }
-            }
-        });
+            });
 
-        let (Some(Some(origin)), Some(Some(destination))) = (node_mapping.get_by_left(&origin), node_mapping.get_by_left(&destination)) else {
+        let [Some(origin), Some(destination)] = node_mapping[..] else {
             return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
                 "origin or destination not found",
             ));
         };
+        let (origin, destination) = (&origin.cell, &destination.cell);
 
         println!(
             "astar from {} to {}",
@@ -510,9 +1099,30 @@ impl PyH3Graph {
             u64::from(destination.cell)
         );
 
-        let astar_res =
+        let constraints = RoutingConstraints {
+            max_transfers,
+            max_walk_time,
+            max_walk_distance,
+        };
+        let astar_res = if !constraints.is_unconstrained() {
+            self.graph.constrained_astar(
+                origin,
+                Some(destination),
+                None,
+                None,
+                None,
+                hour_of_week,
+                &constraints,
+                h,
+                H3Cell::distance_m,
+            )
+        } else if hour_of_week.is_none() {
+            // without time-dependent weights, the bidirectional search explores far fewer nodes
+            self.graph.bidirectional_astar(origin, destination, h)
+        } else {
             self.graph
-                .astar(origin, Some(destination), None, None, None, hour_of_week, h);
+                .astar(origin, Some(destination), None, None, None, hour_of_week, h)
+        };
 
         if let Ok(astar_res) = astar_res {
             if let (Some(path), Some(distance)) = (astar_res.path, astar_res.distances.first()) {
@@ -534,98 +1144,187 @@ impl PyH3Graph {
         }
     }
 
-    pub fn matrix_distance(
+    /// returns up to `k` distinct loopless paths between two cells with their travel times, sorted by travel time
+    pub fn k_shortest_paths(
+        &self,
+        origin: u64,
+        destination: u64,
+        k: usize,
+        hour_of_week: Option<usize>,
+    ) -> PyResult<Vec<(Vec<u64>, f64)>> {
+        let node_mapping = self
+            .graph
+            .snap_ids(&[origin, destination], &self.snap_options);
+
+        let [Some(origin), Some(destination)] = node_mapping[..] else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "origin or destination not found",
+            ));
+        };
+        let (origin, destination) = (&origin.cell, &destination.cell);
+
+        let Ok(paths) = self
+            .graph
+            .k_shortest_paths(origin, destination, k, hour_of_week)
+        else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "no path found",
+            ));
+        };
+
+        Ok(paths
+            .into_iter()
+            .map(|(path, distance)| {
+                (
+                    path.into_iter()
+                        .map(|cell| u64::from(cell.cell))
+                        .collect::<Vec<u64>>(),
+                    distance,
+                )
+            })
+            .collect())
+    }
+
+    /// returns the Pareto set of journeys between two cells, trading off travel time, transfers and walking time
+    ///
+    /// each journey is returned as (path, travel time, transfers, walking time), sorted by travel time
+    #[allow(clippy::type_complexity)]
+    pub fn pareto_paths(
+        &self,
+        origin: u64,
+        destination: u64,
+        hour_of_week: Option<usize>,
+        max_transfers: Option<u32>,
+        max_walk_time: Option<f64>,
+        max_walk_distance: Option<f64>,
+    ) -> PyResult<Vec<(Vec<u64>, f64, u32, f64)>> {
+        let h =
+            |start_cell: &H3Cell, end_cell: &H3Cell| self.heuristic.estimate(start_cell, end_cell);
+
+        let node_mapping = self
+            .graph
+            .snap_ids(&[origin, destination], &self.snap_options);
+
+        let [Some(origin), Some(destination)] = node_mapping[..] else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "origin or destination not found",
+            ));
+        };
+        let (origin, destination) = (&origin.cell, &destination.cell);
+
+        let constraints = RoutingConstraints {
+            max_transfers,
+            max_walk_time,
+            max_walk_distance,
+        };
+        let Ok(journeys) = self.graph.pareto_journeys(
+            origin,
+            destination,
+            None,
+            hour_of_week,
+            &constraints,
+            h,
+            H3Cell::distance_m,
+        ) else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "no path found",
+            ));
+        };
+
+        Ok(journeys
+            .into_iter()
+            .map(|journey| {
+                let u64_path = journey
+                    .path
+                    .into_iter()
+                    .map(|cell| u64::from(cell.cell))
+                    .collect::<Vec<u64>>();
+                (u64_path, journey.time, journey.transfers, journey.walk_time)
+            })
+            .collect())
+    }
+
+    /// computes the travel times between all origins and destinations as an `OdMatrix`
+    ///
+    /// the rows and columns follow the given order, duplicates and ids outside the graph are kept
+    #[allow(clippy::too_many_arguments)]
+    pub fn od_matrix(
         &self,
         origins: Vec<u64>,
         destinations: Vec<u64>,
         hour_of_week: Option<usize>,
         infinity: Option<f64>,
         dynamic_infinity: Option<bool>,
-    ) -> PyResult<HashMap<u64, Vec<Option<f64>>>> {
-        fn h(_start_cell: &H3Cell, _end_cell: &H3Cell) -> f64 {
-            1.0
+        max_transfers: Option<u32>,
+        max_walk_time: Option<f64>,
+        max_walk_distance: Option<f64>,
+    ) -> PyOdMatrix {
+        let constraints = RoutingConstraints {
+            max_transfers,
+            max_walk_time,
+            max_walk_distance,
+        };
+        PyOdMatrix {
+            matrix: self.graph.od_matrix(
+                origins,
+                destinations,
+                &self.snap_options,
+                hour_of_week,
+                infinity,
+                dynamic_infinity,
+                &constraints,
+            ),
         }
+    }
 
-        // map each origin and destination to an H3 cell that is present in the graph
-        let node_map_access = self.graph.node_map.as_ref().read().unwrap();
-
-        let origins = u64list_to_h3cells(&node_map_access, origins, self.k_ring);
-        let destinations = u64list_to_h3cells(&node_map_access, destinations, self.k_ring);
-
-        let distances = self.graph.matrix_astar_distance(
-            &origins.iter().filter_map(|(_, c)| *c).collect::<Vec<_>>(),
-            Some(
-                &destinations
-                    .iter()
-                    .filter_map(|(_, c)| *c)
-                    .collect::<Vec<_>>(),
-            ),
-            false,
+    /// returns one row per origin, each row has one entry per destination in the order of `destinations`
+    #[allow(clippy::too_many_arguments)]
+    pub fn matrix_distance(
+        &self,
+        origins: Vec<u64>,
+        destinations: Vec<u64>,
+        hour_of_week: Option<usize>,
+        infinity: Option<f64>,
+        dynamic_infinity: Option<bool>,
+        max_transfers: Option<u32>,
+        max_walk_time: Option<f64>,
+        max_walk_distance: Option<f64>,
+    ) -> PyResult<HashMap<u64, Vec<Option<f64>>>> {
+        let constraints = RoutingConstraints {
+            max_transfers,
+            max_walk_time,
+            max_walk_distance,
+        };
+        let matrix = self.graph.od_matrix(
+            origins,
+            destinations,
+            &self.snap_options,
             hour_of_week,
             infinity,
             dynamic_infinity,
-            h,
+            &constraints,
         );
 
-        // println!(
-        //     "matrix distance computed for {} origins - got {} results",
-        //     origins.len(),
-        //     distances.len()
-        // );
-
-        Ok(distances
-            .into_par_iter()
-            .map(|(graph_origin, distances)| {
-                let original_origin: u64 = *origins.get_by_right(&Some(graph_origin)).unwrap();
-                if let Ok(row) = distances {
-                    (original_origin, row)
-                } else {
-                    (original_origin, vec![None])
-                }
-            })
+        Ok(matrix
+            .origins
+            .iter()
+            .enumerate()
+            .map(|(row, origin)| (*origin, matrix.row(row)))
             .collect())
     }
 }
 
-/// returns processed H3 cells in a list of tuples (original H3 input, mapped H3 cell)
-///
-/// H3 cells that are not present in the graph are mapped to their first neighbor that is present in the graph
-/// or none if no cells can be found within a k-ring of size 2
-fn u64list_to_h3cells(
-    node_access: &RwLockReadGuard<BiHashMap<H3Cell, usize>>,
-    list: Vec<u64>,
-    k_ring: u32,
-) -> BiHashMap<u64, Option<H3Cell>> {
-    list.into_iter()
-        .filter_map(|origin| {
-            let cell_index: h3o::CellIndex = origin.try_into().ok()?;
-            let cell = H3Cell {
-                cell: cell_index,
-                layer: -1,
-            };
-            if node_access.contains_left(&cell) {
-                Some((origin, Some(cell)))
-            } else {
-                let neighbors = cell_index.grid_ring_fast(k_ring);
-                for neighbor in neighbors.flatten() {
-                    let neighbor_cell = H3Cell {
-                        cell: neighbor,
-                        layer: -1,
-                    };
-                    if node_access.contains_left(&neighbor_cell) {
-                        return Some((origin, Some(neighbor_cell)));
-                    }
-                }
-                Some((origin, None))
-            }
-        })
-        .collect::<BiHashMap<_, _>>()
-}
-
 #[cfg(feature = "pyo3")]
 impl Default for PyH3Graph {
     fn default() -> Self {
-        Self::new(Some(WeightModifier::default()), 2, "all".to_string())
+        Self::new(
+            Some(WeightModifier::default()),
+            None,
+            "all".to_string(),
+            None,
+            None,
+        )
+        .unwrap()
     }
 }
 
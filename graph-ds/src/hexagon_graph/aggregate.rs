@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::str::FromStr;
+
+use h3o::{CellIndex, LatLng, Resolution};
+use serde_json::{json, Value};
+
+use super::{
+    h3cell::H3Cell,
+    spatial_index::SpatialIndex,
+    subgraph::{polygons_from_geojson, Polygon},
+};
+
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+
+/// a summary statistic of the values within an area
+///
+/// values that are not finite (e.g. unreachable cells) are counted, but only `ShareBelow` takes them into
+/// account, as not below the threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Statistic {
+    Mean,
+    Median,
+    Min,
+    /// mean weighted by a per-cell weight, usually the population
+    WeightedMean,
+    /// the share of values below the threshold, weighted if weights are given
+    ShareBelow(f64),
+}
+
+impl FromStr for Statistic {
+    type Err = anyhow::Error;
+
+    /// "mean", "median", "min", "weighted_mean" or "share_below:<threshold>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("share_below", threshold)) => Ok(Statistic::ShareBelow(threshold.parse()?)),
+            None if s == "mean" => Ok(Statistic::Mean),
+            None if s == "median" => Ok(Statistic::Median),
+            None if s == "min" => Ok(Statistic::Min),
+            None if s == "weighted_mean" => Ok(Statistic::WeightedMean),
+            _ => Err(anyhow::anyhow!("unknown statistic {s}")),
+        }
+    }
+}
+
+impl Statistic {
+    pub fn name(&self) -> String {
+        match self {
+            Statistic::Mean => "mean".to_string(),
+            Statistic::Median => "median".to_string(),
+            Statistic::Min => "min".to_string(),
+            Statistic::WeightedMean => "weighted_mean".to_string(),
+            Statistic::ShareBelow(threshold) => format!("share_below_{threshold}"),
+        }
+    }
+
+    /// `values` are (value, weight) pairs, the weight is 1 if no weights are given
+    fn compute(&self, values: &[(f64, f64)]) -> Option<f64> {
+        let finite = values
+            .iter()
+            .filter(|(value, _)| value.is_finite())
+            .cloned()
+            .collect::<Vec<_>>();
+        match self {
+            Statistic::Mean => (!finite.is_empty())
+                .then(|| finite.iter().map(|(value, _)| value).sum::<f64>() / finite.len() as f64),
+            Statistic::Median => {
+                let mut sorted = finite.iter().map(|(value, _)| *value).collect::<Vec<_>>();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                match sorted.len() {
+                    0 => None,
+                    n if n % 2 == 1 => Some(sorted[n / 2]),
+                    n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0),
+                }
+            }
+            Statistic::Min => finite.iter().map(|(value, _)| *value).reduce(f64::min),
+            Statistic::WeightedMean => {
+                let total_weight = finite.iter().map(|(_, weight)| weight).sum::<f64>();
+                (total_weight > 0.0).then(|| {
+                    finite
+                        .iter()
+                        .map(|(value, weight)| value * weight)
+                        .sum::<f64>()
+                        / total_weight
+                })
+            }
+            Statistic::ShareBelow(threshold) => {
+                let total_weight = values.iter().map(|(_, weight)| weight).sum::<f64>();
+                (total_weight > 0.0).then(|| {
+                    finite
+                        .iter()
+                        .filter(|(value, _)| value < threshold)
+                        .fold(0.0, |share, (_, weight)| share + weight)
+                        / total_weight
+                })
+            }
+        }
+    }
+}
+
+/// a named area with one or more polygons
+#[derive(Debug, Clone)]
+pub struct District {
+    pub name: String,
+    pub polygons: Vec<Polygon>,
+}
+
+/// reads the districts of a GeoJSON feature collection, the name is taken from the `name_property`
+/// of each feature or its position in the collection
+pub fn districts_from_geojson(geojson: &str, name_property: &str) -> anyhow::Result<Vec<District>> {
+    let value: Value = serde_json::from_str(geojson)?;
+    let features = value
+        .get("features")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("districts have to be a feature collection"))?;
+    features
+        .iter()
+        .enumerate()
+        .map(|(idx, feature)| {
+            let name = match feature.get("properties").and_then(|p| p.get(name_property)) {
+                Some(Value::String(name)) => name.clone(),
+                Some(Value::Null) | None => idx.to_string(),
+                Some(other) => other.to_string(),
+            };
+            Ok(District {
+                name,
+                polygons: polygons_from_geojson(feature)?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct AggregateRow {
+    /// the parent cell id or the district name
+    pub key: String,
+    /// number of input cells in the area
+    pub count: usize,
+    /// one value per statistic of the table
+    pub values: Vec<Option<f64>>,
+    /// polygons as (exterior, holes) for the GeoJSON output
+    pub geometry: Vec<Polygon>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AggregateTable {
+    pub statistics: Vec<Statistic>,
+    pub rows: Vec<AggregateRow>,
+}
+
+impl AggregateTable {
+    #[allow(clippy::type_complexity)]
+    fn from_groups(
+        statistics: &[Statistic],
+        groups: Vec<(String, Vec<Polygon>, Vec<(f64, f64)>)>,
+    ) -> Self {
+        let rows = groups
+            .into_iter()
+            .map(|(key, geometry, values)| AggregateRow {
+                key,
+                count: values.len(),
+                values: statistics
+                    .iter()
+                    .map(|statistic| statistic.compute(&values))
+                    .collect(),
+                geometry,
+            })
+            .collect();
+        AggregateTable {
+            statistics: statistics.to_vec(),
+            rows,
+        }
+    }
+
+    /// writes one line per area with the key, the number of cells and the statistics, empty values are left blank
+    pub fn write_csv(&self, writer: &mut impl Write) -> anyhow::Result<()> {
+        let header = ["key".to_string(), "count".to_string()]
+            .into_iter()
+            .chain(self.statistics.iter().map(Statistic::name))
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", header.join(","))?;
+        for row in &self.rows {
+            let values = row
+                .values
+                .iter()
+                .map(|value| value.map_or(String::new(), |value| value.to_string()));
+            let line = [csv_field(&row.key), row.count.to_string()]
+                .into_iter()
+                .chain(values)
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", line.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// a feature collection with one (multi)polygon feature per area and the statistics as properties
+    pub fn to_geojson(&self) -> Value {
+        let ring = |ring: &Vec<LatLng>| {
+            let mut positions = ring
+                .iter()
+                .map(|point| json!([point.lng(), point.lat()]))
+                .collect::<Vec<_>>();
+            // GeoJSON rings are closed
+            if let Some(first) = positions.first().cloned() {
+                if positions.last() != Some(&first) {
+                    positions.push(first);
+                }
+            }
+            positions
+        };
+        let polygon = |(exterior, holes): &Polygon| {
+            std::iter::once(ring(exterior))
+                .chain(holes.iter().map(ring))
+                .collect::<Vec<_>>()
+        };
+        let features = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut properties = serde_json::Map::new();
+                properties.insert("key".to_string(), json!(row.key));
+                properties.insert("count".to_string(), json!(row.count));
+                for (statistic, value) in self.statistics.iter().zip(row.values.iter()) {
+                    properties.insert(statistic.name(), json!(value));
+                }
+                let geometry = match row.geometry.as_slice() {
+                    [single] => json!({"type": "Polygon", "coordinates": polygon(single)}),
+                    polygons => json!({
+                        "type": "MultiPolygon",
+                        "coordinates": polygons.iter().map(polygon).collect::<Vec<_>>(),
+                    }),
+                };
+                json!({"type": "Feature", "properties": properties, "geometry": geometry})
+            })
+            .collect::<Vec<_>>();
+        json!({"type": "FeatureCollection", "features": features})
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// pairs every value with its weight, or 1 without weights
+fn weighted(values: &[f64], weights: Option<&[f64]>) -> anyhow::Result<Vec<(f64, f64)>> {
+    match weights {
+        Some(weights) if weights.len() != values.len() => Err(anyhow::anyhow!(
+            "got {} weights for {} values",
+            weights.len(),
+            values.len()
+        )),
+        Some(weights) => Ok(values
+            .iter()
+            .cloned()
+            .zip(weights.iter().cloned())
+            .collect()),
+        None => Ok(values.iter().map(|value| (*value, 1.0)).collect()),
+    }
+}
+
+fn check_statistics(statistics: &[Statistic], weights: Option<&[f64]>) -> anyhow::Result<()> {
+    if weights.is_none() && statistics.contains(&Statistic::WeightedMean) {
+        return Err(anyhow::anyhow!("the weighted mean needs weights"));
+    }
+    Ok(())
+}
+
+/// rolls per-cell values up to their parent cells at `resolution`, sorted by parent cell
+pub fn aggregate_to_parents(
+    cells: &[CellIndex],
+    values: &[f64],
+    weights: Option<&[f64]>,
+    resolution: Resolution,
+    statistics: &[Statistic],
+) -> anyhow::Result<AggregateTable> {
+    check_statistics(statistics, weights)?;
+    if cells.len() != values.len() {
+        return Err(anyhow::anyhow!(
+            "got {} values for {} cells",
+            values.len(),
+            cells.len()
+        ));
+    }
+    let mut groups: HashMap<CellIndex, Vec<(f64, f64)>> = HashMap::new();
+    for (cell, value) in cells.iter().zip(weighted(values, weights)?) {
+        let parent = cell.parent(resolution).ok_or_else(|| {
+            anyhow::anyhow!("cell {cell} is coarser than resolution {resolution}")
+        })?;
+        groups.entry(parent).or_default().push(value);
+    }
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(parent, _)| u64::from(*parent));
+
+    Ok(AggregateTable::from_groups(
+        statistics,
+        groups
+            .into_iter()
+            .map(|(parent, values)| {
+                let boundary = parent.boundary().to_vec();
+                (parent.to_string(), vec![(boundary, vec![])], values)
+            })
+            .collect(),
+    ))
+}
+
+/// rolls per-cell values up to districts, a cell belongs to every district that contains its center
+///
+/// all cells need the same resolution. A cell in several overlapping polygons of one district is counted once
+pub fn aggregate_to_districts(
+    cells: &[CellIndex],
+    values: &[f64],
+    weights: Option<&[f64]>,
+    districts: &[District],
+    statistics: &[Statistic],
+) -> anyhow::Result<AggregateTable> {
+    check_statistics(statistics, weights)?;
+    if cells.len() != values.len() {
+        return Err(anyhow::anyhow!(
+            "got {} values for {} cells",
+            values.len(),
+            cells.len()
+        ));
+    }
+    if let Some(first) = cells.first() {
+        if let Some(cell) = cells
+            .iter()
+            .find(|cell| cell.resolution() != first.resolution())
+        {
+            return Err(anyhow::anyhow!(
+                "all cells need the same resolution, got {} and {}",
+                first.resolution(),
+                cell.resolution()
+            ));
+        }
+    }
+    let values = weighted(values, weights)?;
+    let mut positions: HashMap<CellIndex, Vec<usize>> = HashMap::new();
+    for (position, cell) in cells.iter().enumerate() {
+        positions.entry(*cell).or_default().push(position);
+    }
+    let index = SpatialIndex::new(positions.keys().map(|cell| H3Cell {
+        cell: *cell,
+        layer: -1,
+    }));
+
+    Ok(AggregateTable::from_groups(
+        statistics,
+        districts
+            .iter()
+            .map(|district| {
+                let district_values = district
+                    .polygons
+                    .iter()
+                    .flat_map(|(exterior, holes)| index.in_polygon(exterior, holes))
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .flat_map(|cell| positions[&cell.cell].iter())
+                    .map(|position| values[*position])
+                    .collect::<Vec<_>>();
+                (
+                    district.name.clone(),
+                    district.polygons.clone(),
+                    district_values,
+                )
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(feature = "pyo3")]
+fn write_outputs(
+    table: &AggregateTable,
+    csv_path: Option<String>,
+    geojson_path: Option<String>,
+) -> anyhow::Result<()> {
+    if let Some(csv_path) = csv_path {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(csv_path)?);
+        table.write_csv(&mut file)?;
+    }
+    if let Some(geojson_path) = geojson_path {
+        std::fs::write(geojson_path, table.to_geojson().to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "pyo3")]
+fn parse_inputs(
+    cells: Vec<u64>,
+    statistics: Vec<String>,
+) -> anyhow::Result<(Vec<CellIndex>, Vec<Statistic>)> {
+    let cells = cells
+        .into_iter()
+        .map(CellIndex::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let statistics = statistics
+        .iter()
+        .map(|statistic| statistic.parse())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok((cells, statistics))
+}
+
+/// (key, count, statistics) per area
+#[cfg(feature = "pyo3")]
+type PyAggregateRows = Vec<(String, usize, Vec<Option<f64>>)>;
+
+#[cfg(feature = "pyo3")]
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string())
+}
+
+#[cfg(feature = "pyo3")]
+fn to_py_rows(table: AggregateTable) -> PyAggregateRows {
+    table
+        .rows
+        .into_iter()
+        .map(|row| (row.key, row.count, row.values))
+        .collect()
+}
+
+/// aggregates per-cell values to parent cells
+///
+/// statistics are "mean", "median", "min", "weighted_mean" or "share_below:<threshold>",
+/// returns (parent cell, count, statistics) per parent cell
+#[cfg(feature = "pyo3")]
+#[pyfunction]
+#[pyo3(name = "aggregate_to_parents")]
+#[allow(clippy::too_many_arguments)]
+pub fn py_aggregate_to_parents(
+    cells: Vec<u64>,
+    values: Vec<f64>,
+    resolution: u8,
+    statistics: Vec<String>,
+    weights: Option<Vec<f64>>,
+    csv_path: Option<String>,
+    geojson_path: Option<String>,
+) -> PyResult<PyAggregateRows> {
+    let (cells, statistics) = parse_inputs(cells, statistics).map_err(to_py_err)?;
+    let resolution = Resolution::try_from(resolution)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    let table = aggregate_to_parents(&cells, &values, weights.as_deref(), resolution, &statistics)
+        .map_err(to_py_err)?;
+    write_outputs(&table, csv_path, geojson_path).map_err(to_py_err)?;
+    Ok(to_py_rows(table))
+}
+
+/// aggregates per-cell values to the districts of a GeoJSON feature collection
+///
+/// the district names are read from the `name_property` of each feature ("name" by default),
+/// returns (district name, count, statistics) per district
+#[cfg(feature = "pyo3")]
+#[pyfunction]
+#[pyo3(name = "aggregate_to_districts")]
+#[allow(clippy::too_many_arguments)]
+pub fn py_aggregate_to_districts(
+    cells: Vec<u64>,
+    values: Vec<f64>,
+    districts_geojson: String,
+    statistics: Vec<String>,
+    weights: Option<Vec<f64>>,
+    name_property: Option<String>,
+    csv_path: Option<String>,
+    geojson_path: Option<String>,
+) -> PyResult<PyAggregateRows> {
+    let (cells, statistics) = parse_inputs(cells, statistics).map_err(to_py_err)?;
+    let districts = districts_from_geojson(
+        &districts_geojson,
+        name_property.as_deref().unwrap_or("name"),
+    )
+    .map_err(to_py_err)?;
+    let table =
+        aggregate_to_districts(&cells, &values, weights.as_deref(), &districts, &statistics)
+            .map_err(to_py_err)?;
+    write_outputs(&table, csv_path, geojson_path).map_err(to_py_err)?;
+    Ok(to_py_rows(table))
+}
@@ -0,0 +1,68 @@
+use std::collections::{HashMap, HashSet};
+
+use h3o::{CellIndex, LatLng};
+
+use crate::Graph;
+
+use super::{h3cell::H3Cell, osm::OSMLayer, spatial_index::SpatialIndex, WeightModifier};
+
+impl Graph<H3Cell> {
+    /// the base layer cells where a transit route can be boarded
+    pub fn boarding_cells(&self) -> Vec<H3Cell> {
+        let nodes = self.nodes.as_ref().read().unwrap();
+        let edges = self.edges.as_ref().read().unwrap();
+        edges
+            .values()
+            .flatten()
+            .filter_map(|edge| {
+                let (Some(Some(from)), Some(Some(to))) = (nodes.get(edge.from), nodes.get(edge.to))
+                else {
+                    return None;
+                };
+                (from.id.layer == OSMLayer::Walking.get_id() && to.id.layer >= 0).then_some(from.id)
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// connects the bike layer to the walking network at bicycle parking near transit stops
+    ///
+    /// every parking cell with a boarding cell within `max_distance_m` is linked from the closest bike layer node
+    /// to the closest base layer node. The weight is the parking penalty plus the ride to the parking, the walk
+    /// to the stop follows the walking edges and counts towards walking limits. Transit layers have to be merged
+    /// before, returns the number of added edges
+    pub fn connect_bike_and_ride(
+        &mut self,
+        bike_parking: &HashSet<CellIndex>,
+        max_distance_m: f64,
+        options: &WeightModifier,
+    ) -> anyhow::Result<usize> {
+        let stops = SpatialIndex::new(self.boarding_cells());
+        let bikes = self.spatial_index(OSMLayer::Cycling.get_id());
+        let walks = self.spatial_index(OSMLayer::Walking.get_id());
+
+        let mut links: HashMap<(H3Cell, H3Cell), f64> = HashMap::new();
+        for parking in bike_parking {
+            let parking = LatLng::from(*parking);
+            if stops.within(&parking, max_distance_m).is_empty() {
+                continue;
+            }
+            let (Some((bike_cell, ride_m)), Some((base_cell, _))) = (
+                bikes.nearest(&parking, max_distance_m),
+                walks.nearest(&parking, max_distance_m),
+            ) else {
+                continue;
+            };
+            let weight = options.bike_parking_penalty + ride_m / options.bike_speed / 60.0;
+            let link = links.entry((bike_cell, base_cell)).or_insert(weight);
+            *link = link.min(weight);
+        }
+
+        let added = links.len();
+        for ((bike_cell, base_cell), weight) in links {
+            self.build_and_add_egde(bike_cell, base_cell, Some(weight), None, None)?;
+        }
+        Ok(added)
+    }
+}
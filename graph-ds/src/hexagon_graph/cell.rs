@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::Layered;
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy)]
 pub enum Direction {
@@ -219,3 +221,9 @@ impl HexCell {
         }
     }
 }
+
+impl Layered for HexCell {
+    fn layer(&self) -> i16 {
+        self.layer
+    }
+}
@@ -0,0 +1,156 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use h3o::{LatLng, Resolution};
+use rayon::prelude::*;
+
+use crate::{DijkstraNode, Edge, Graph};
+
+use super::h3cell::H3Cell;
+
+impl Graph<H3Cell> {
+    /// aggregates the graph to a coarser H3 resolution
+    ///
+    /// the nodes are merged by their parent cell per layer. Every merged cell is represented by the node closest
+    /// to the center of the parent cell, and the weight of a coarse edge is the shortest travel time between the
+    /// representatives of both cells, searched within the cell and its direct neighbors. If that search fails,
+    /// the fastest original edge between both cells is used.
+    ///
+    /// only the static edge weights are used, time-dependent weight lists are dropped
+    pub fn coarsen(&self, resolution: Resolution) -> anyhow::Result<Graph<H3Cell>> {
+        let nodes = self.nodes.as_ref().read().unwrap();
+        let edges = self.edges.as_ref().read().unwrap();
+
+        // the merged cell of every node index
+        let groups = nodes
+            .iter()
+            .map(|node| {
+                let node = node.as_ref()?;
+                if node.id.cell.resolution() < resolution {
+                    return None;
+                }
+                Some(H3Cell {
+                    cell: node.id.cell.parent(resolution)?,
+                    layer: node.id.layer,
+                })
+            })
+            .collect::<Vec<_>>();
+        if groups.iter().all(Option::is_none) {
+            return Err(anyhow::anyhow!(
+                "no nodes can be merged at resolution {resolution}"
+            ));
+        }
+
+        let mut representatives: HashMap<H3Cell, (usize, f64)> = HashMap::new();
+        for (idx, group) in groups.iter().enumerate() {
+            let (Some(group), Some(node)) = (group, &nodes[idx]) else {
+                continue;
+            };
+            let distance = LatLng::from(node.id.cell).distance_m(LatLng::from(group.cell));
+            let representative = representatives.entry(*group).or_insert((idx, distance));
+            if distance < representative.1 {
+                *representative = (idx, distance);
+            }
+        }
+
+        // the fastest original edge between every pair of neighboring merged cells
+        let mut fallback: HashMap<(H3Cell, H3Cell), f64> = HashMap::new();
+        for edge in edges.values().flatten() {
+            let (Some(from), Some(to)) = (groups[edge.from], groups[edge.to]) else {
+                continue;
+            };
+            if from == to {
+                continue;
+            }
+            let weight = edge.weight.unwrap_or(1.0);
+            let entry = fallback.entry((from, to)).or_insert(weight);
+            *entry = entry.min(weight);
+        }
+        let mut neighbors: HashMap<H3Cell, HashSet<H3Cell>> = HashMap::new();
+        for (from, to) in fallback.keys() {
+            neighbors.entry(*from).or_default().insert(*to);
+        }
+
+        let coarse_edges = neighbors
+            .par_iter()
+            .flat_map_iter(|(from, targets)| {
+                let allowed = targets
+                    .iter()
+                    .chain(std::iter::once(from))
+                    .cloned()
+                    .collect::<HashSet<_>>();
+                let target_idx = targets
+                    .iter()
+                    .map(|target| representatives[target].0)
+                    .collect::<Vec<_>>();
+                let distances =
+                    local_dijkstra(&edges, representatives[from].0, &target_idx, |idx| {
+                        groups[idx].is_some_and(|group| allowed.contains(&group))
+                    });
+                targets
+                    .iter()
+                    .zip(distances)
+                    .map(|(to, distance)| {
+                        let weight = distance.unwrap_or(fallback[&(*from, *to)]);
+                        (*from, *to, weight)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        drop(nodes);
+        drop(edges);
+
+        let mut coarse = Graph::<H3Cell>::new();
+        for (from, to, weight) in coarse_edges {
+            coarse.build_and_add_egde(from, to, Some(weight), None, None)?;
+        }
+        Ok(coarse)
+    }
+}
+
+/// Dijkstra search that only visits allowed nodes, returns the distance to each target
+fn local_dijkstra(
+    edges: &HashMap<usize, HashSet<Edge>, nohash::BuildNoHashHasher<usize>>,
+    start: usize,
+    targets: &[usize],
+    is_allowed: impl Fn(usize) -> bool,
+) -> Vec<Option<f64>> {
+    let mut open_targets = targets.iter().cloned().collect::<HashSet<_>>();
+    let mut distances: HashMap<usize, f64> = HashMap::new();
+    let mut settled: HashSet<usize> = HashSet::new();
+    let mut q: BinaryHeap<Reverse<DijkstraNode>> = BinaryHeap::new();
+
+    distances.insert(start, 0.0);
+    q.push(Reverse(DijkstraNode {
+        id: start,
+        distance: 0.0,
+    }));
+
+    while let Some(Reverse(current)) = q.pop() {
+        if !settled.insert(current.id) {
+            continue;
+        }
+        open_targets.remove(&current.id);
+        if open_targets.is_empty() {
+            break;
+        }
+        for edge in edges.get(&current.id).into_iter().flatten() {
+            if !is_allowed(edge.to) {
+                continue;
+            }
+            let distance = current.distance + edge.weight.unwrap_or(1.0);
+            if distance < *distances.get(&edge.to).unwrap_or(&f64::INFINITY) {
+                distances.insert(edge.to, distance);
+                q.push(Reverse(DijkstraNode {
+                    id: edge.to,
+                    distance,
+                }));
+            }
+        }
+    }
+
+    targets
+        .iter()
+        .map(|target| settled.contains(target).then(|| distances[target]))
+        .collect()
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, path::Path};
+
+use h3o::LatLng;
+use serde::Deserialize;
+
+use crate::Graph;
+
+use super::{h3cell::H3Cell, osm::OSMLayer, WeightModifier};
+
+#[derive(Debug, Deserialize)]
+struct StationInformation {
+    data: StationData,
+}
+
+#[derive(Debug, Deserialize)]
+struct StationData {
+    stations: Vec<Station>,
+}
+
+/// a docking station of a GBFS `station_information.json` feed
+#[derive(Debug, Clone, Deserialize)]
+pub struct Station {
+    pub station_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub lat: f64,
+    pub lon: f64,
+    #[serde(default)]
+    pub capacity: Option<u32>,
+}
+
+/// reads the stations of a local GBFS `station_information.json` snapshot
+pub fn read_stations(path: impl AsRef<Path>) -> anyhow::Result<Vec<Station>> {
+    let information: StationInformation =
+        serde_json::from_str(&std::fs::read_to_string(path.as_ref())?)?;
+    Ok(information.data.stations)
+}
+
+impl Graph<H3Cell> {
+    /// connects the base layer to the bike layer at bike-share docking stations
+    ///
+    /// every station is snapped to the closest walking and the closest bike node within `max_distance_m`.
+    /// Picking up a bike costs `pickup_penalty` and returning it `dropoff_penalty` minutes, plus the walk between
+    /// the station and the snapped nodes. Stations with invalid coordinates are skipped.
+    /// Returns the number of connected stations and the number of invalid stations
+    pub fn connect_bike_share(
+        &mut self,
+        stations: &[Station],
+        max_distance_m: f64,
+        options: &WeightModifier,
+    ) -> anyhow::Result<(usize, usize)> {
+        let walking = self.spatial_index(OSMLayer::Walking.get_id());
+        let bikes = self.spatial_index(OSMLayer::Cycling.get_id());
+
+        let mut pickups: HashMap<(H3Cell, H3Cell), f64> = HashMap::new();
+        let (mut connected, mut invalid) = (0, 0);
+        for station in stations {
+            let Ok(location) = LatLng::new(station.lat, station.lon) else {
+                invalid += 1;
+                continue;
+            };
+            let (Some((walk_cell, walk_m)), Some((bike_cell, bike_m))) = (
+                walking.nearest(&location, max_distance_m),
+                bikes.nearest(&location, max_distance_m),
+            ) else {
+                continue;
+            };
+            let walk = (walk_m + bike_m) / options.walk_speed / 60.0;
+            for (from, to, penalty) in [
+                (walk_cell, bike_cell, options.pickup_penalty),
+                (bike_cell, walk_cell, options.dropoff_penalty),
+            ] {
+                let weight = pickups.entry((from, to)).or_insert(penalty + walk);
+                *weight = weight.min(penalty + walk);
+            }
+            connected += 1;
+        }
+
+        for ((from, to), weight) in pickups {
+            self.build_and_add_egde(from, to, Some(weight), None, None)?;
+        }
+        Ok((connected, invalid))
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use h3o::CellIndex;
 use rayon::prelude::*;
 
+use gtfs_structures::Availability;
 use itertools::Itertools;
 
 #[allow(clippy::type_complexity)]
@@ -12,6 +13,68 @@ pub struct GtfsProcessingResult {
     pub stop_frequencies: HashMap<(CellIndex, usize), Vec<f64>>,
 }
 
+/// the wheelchair accessibility a stop (`wheelchair_boarding`) and a trip (`wheelchair_accessible`) need to be
+/// part of the transit layers
+///
+/// stops without a value take the one of their parent station
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WheelchairAccess {
+    /// all stops and trips
+    #[default]
+    Ignore,
+    /// leaves out stops and trips marked as not accessible
+    NotExcluded,
+    /// only stops and trips marked as accessible
+    Accessible,
+}
+
+impl WheelchairAccess {
+    pub fn allows(&self, availability: &Availability) -> bool {
+        match self {
+            WheelchairAccess::Ignore => true,
+            WheelchairAccess::NotExcluded => *availability != Availability::NotAvailable,
+            WheelchairAccess::Accessible => *availability == Availability::Available,
+        }
+    }
+}
+
+/// removes the trips and the stop times of stops a wheelchair user can not use, the remaining stops of a trip
+/// stay connected
+fn filter_wheelchair_access(feed: &mut gtfs_structures::Gtfs, wheelchair: WheelchairAccess) {
+    let accessible_stops = feed
+        .stops
+        .values()
+        .filter(|stop| {
+            let boarding = match (&stop.wheelchair_boarding, &stop.parent_station) {
+                (Availability::InformationNotAvailable, Some(parent)) => feed
+                    .stops
+                    .get(parent)
+                    .map_or(&stop.wheelchair_boarding, |parent| {
+                        &parent.wheelchair_boarding
+                    }),
+                (boarding, _) => boarding,
+            };
+            wheelchair.allows(boarding)
+        })
+        .map(|stop| stop.id.clone())
+        .collect::<HashSet<_>>();
+
+    let nr_trips = feed.trips.len();
+    feed.trips
+        .retain(|_, trip| wheelchair.allows(&trip.wheelchair_accessible));
+    for trip in feed.trips.values_mut() {
+        trip.stop_times
+            .retain(|stop_time| accessible_stops.contains(&stop_time.stop.id));
+    }
+    println!(
+        "wheelchair access: {} of {} stops, {} of {} trips",
+        accessible_stops.len(),
+        feed.stops.len(),
+        feed.trips.len(),
+        nr_trips
+    );
+}
+
 /// calculates the frequencies at each stop for every route
 ///
 /// frequencies are stored per hour of the week, the frequency number is the number of departures per hour
@@ -150,14 +213,17 @@ pub fn calculate_edge_data(
 }
 
 /// process the GTFS feed and return both the edge data and the stop frequencies
+///
+/// with `wheelchair` other than `WheelchairAccess::Ignore`, only accessible stops and trips are kept
 pub fn process_gtfs(
     url: &str,
     route_index_offset: usize,
     h3_resolution: h3o::Resolution,
+    wheelchair: WheelchairAccess,
 ) -> anyhow::Result<GtfsProcessingResult> {
     println!("getting GTFS feed from {url}");
 
-    let feed = if let Ok(feed_fast) = gtfs_structures::GtfsReader::default()
+    let mut feed = if let Ok(feed_fast) = gtfs_structures::GtfsReader::default()
         .trim_fields(false)
         .read(url)
     {
@@ -167,6 +233,9 @@ pub fn process_gtfs(
             .trim_fields(true)
             .read(url)?
     };
+    if wheelchair != WheelchairAccess::Ignore {
+        filter_wheelchair_access(&mut feed, wheelchair);
+    }
 
     let route_data: HashMap<String, usize> = feed
         .routes
@@ -4,8 +4,10 @@ use std::{
 };
 
 use h3o::{CellIndex, LatLng, Resolution};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::cell::HexCell;
+use crate::Layered;
 
 /// A H3 cell
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -39,6 +41,11 @@ impl H3Cell {
         })
     }
 
+    /// great circle distance between the cell centers in meters
+    pub fn distance_m(&self, other: &H3Cell) -> f64 {
+        LatLng::from(self.cell).distance_m(LatLng::from(other.cell))
+    }
+
     pub fn cell_hash(&self) -> u64 {
         let mut h = DefaultHasher::new();
         self.cell.hash(&mut h);
@@ -63,3 +70,24 @@ impl H3Cell {
         })
     }
 }
+
+impl Layered for H3Cell {
+    fn layer(&self) -> i16 {
+        self.layer
+    }
+}
+
+/// stored as the cell index and the layer
+impl Serialize for H3Cell {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (u64::from(self.cell), self.layer).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for H3Cell {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (cell, layer) = <(u64, i16)>::deserialize(deserializer)?;
+        let cell = CellIndex::try_from(cell).map_err(serde::de::Error::custom)?;
+        Ok(H3Cell { cell, layer })
+    }
+}
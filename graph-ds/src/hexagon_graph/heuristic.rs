@@ -0,0 +1,64 @@
+use crate::Graph;
+
+use super::{h3cell::H3Cell, WeightModifier};
+
+/// admissible A* heuristic for H3 graphs with edge weights in minutes
+///
+/// the estimate is the great circle distance to the target divided by the fastest speed of the graph,
+/// so no path can be faster than the estimate. Unlike grid distances, this works across pentagons and
+/// icosahedron faces.
+#[derive(Debug, Clone, Copy)]
+pub struct GeoHeuristic {
+    /// the fastest speed in meters per minute
+    pub max_speed: f64,
+}
+
+impl Default for GeoHeuristic {
+    /// an infinite speed, which turns the estimate into 0
+    fn default() -> Self {
+        GeoHeuristic {
+            max_speed: f64::INFINITY,
+        }
+    }
+}
+
+impl GeoHeuristic {
+    /// uses the fastest of the walking speed, the cycling speed and any link in the graph
+    pub fn for_graph(graph: &Graph<H3Cell>, options: &WeightModifier) -> Self {
+        let configured_speed = options.walk_speed.max(options.bike_speed) * 60.0;
+        GeoHeuristic {
+            max_speed: configured_speed.max(graph.max_edge_speed()),
+        }
+    }
+
+    /// lower bound of the travel time between two cells in minutes
+    pub fn estimate(&self, from: &H3Cell, to: &H3Cell) -> f64 {
+        from.distance_m(to) / self.max_speed
+    }
+}
+
+impl Graph<H3Cell> {
+    /// the fastest speed of any edge in the graph, in meters per minute
+    ///
+    /// this usually is the fastest transit link. Edges without a positive weight between different cells
+    /// result in an infinite speed.
+    pub fn max_edge_speed(&self) -> f64 {
+        let edges = self.edges.as_ref().read().unwrap();
+        let nodes = self.nodes.as_ref().read().unwrap();
+        edges
+            .values()
+            .flatten()
+            .filter_map(|edge| {
+                let (Some(Some(from)), Some(Some(to))) = (nodes.get(edge.from), nodes.get(edge.to))
+                else {
+                    return None;
+                };
+                if from.id.cell == to.id.cell {
+                    return None;
+                }
+                let distance = from.id.distance_m(&to.id);
+                Some(distance / edge.weight.unwrap_or(1.0))
+            })
+            .fold(0.0, f64::max)
+    }
+}
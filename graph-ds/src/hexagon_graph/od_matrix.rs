@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use crate::{constrained::RoutingConstraints, Graph};
+
+use super::{h3cell::H3Cell, snapping::SnapOptions};
+
+#[cfg(feature = "pyo3")]
+use numpy::{ndarray::Array2, IntoPyArray, PyArray1, PyArray2};
+#[cfg(feature = "pyo3")]
+use pyo3::prelude::*;
+
+/// the state of a single origin-destination pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OdStatus {
+    /// both ids are cells in the graph and a path was found
+    Reached = 0,
+    /// a path was found, but the origin or the destination was snapped to a neighboring cell
+    Snapped = 1,
+    /// both ids were mapped to the graph, but no path was found within the cutoff
+    Unreachable = 2,
+    /// the origin or the destination could not be mapped to a cell in the graph
+    NotInGraph = 3,
+}
+
+/// origin-destination matrix, rows follow the origins and columns follow the destinations as they were given
+///
+/// the distances are stored densely in row-major order, pairs without a distance are NaN
+#[derive(Debug, Clone)]
+pub struct OdMatrix {
+    pub origins: Vec<u64>,
+    pub destinations: Vec<u64>,
+    pub origin_cells: Vec<Option<H3Cell>>,
+    pub destination_cells: Vec<Option<H3Cell>>,
+    pub distances: Vec<f64>,
+    pub status: Vec<OdStatus>,
+}
+
+impl OdMatrix {
+    /// (number of origins, number of destinations)
+    pub fn shape(&self) -> (usize, usize) {
+        (self.origins.len(), self.destinations.len())
+    }
+
+    /// the distance between the origin at `row` and the destination at `column`
+    pub fn get(&self, row: usize, column: usize) -> Option<f64> {
+        let distance = self.distances[row * self.destinations.len() + column];
+        (!distance.is_nan()).then_some(distance)
+    }
+
+    pub fn get_status(&self, row: usize, column: usize) -> OdStatus {
+        self.status[row * self.destinations.len() + column]
+    }
+
+    /// the distances from the origin at `row` to all destinations
+    pub fn row(&self, row: usize) -> Vec<Option<f64>> {
+        (0..self.destinations.len())
+            .map(|column| self.get(row, column))
+            .collect()
+    }
+}
+
+impl Graph<H3Cell> {
+    /// computes the travel times between all origins and destinations, given as H3 cell ids
+    ///
+    /// the ids are snapped to the graph with `snap_options`. Each snapped cell is only searched once,
+    /// no matter how often it appears in the input.
+    /// * if `constraints` is unconstrained, a parallel A* search is used, otherwise the constrained search
+    #[allow(clippy::too_many_arguments)]
+    pub fn od_matrix(
+        &self,
+        origins: Vec<u64>,
+        destinations: Vec<u64>,
+        snap_options: &SnapOptions,
+        weight_list_index: Option<usize>,
+        infinity: Option<f64>,
+        dynamic_infinity: Option<bool>,
+        constraints: &RoutingConstraints,
+    ) -> OdMatrix {
+        let snap = |ids: &[u64]| {
+            self.snap_ids(ids, snap_options)
+                .into_iter()
+                .map(|snapped| snapped.map(|snapped| snapped.cell))
+                .collect::<Vec<_>>()
+        };
+        let origin_cells = snap(&origins);
+        let destination_cells = snap(&destinations);
+
+        // every distinct cell is searched only once
+        let mut unique_origins = origin_cells.iter().flatten().cloned().collect::<Vec<_>>();
+        unique_origins.sort();
+        unique_origins.dedup();
+        let mut unique_destinations = destination_cells
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        unique_destinations.sort();
+        unique_destinations.dedup();
+        let columns = unique_destinations
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| (*cell, column))
+            .collect::<HashMap<_, _>>();
+
+        let rows = if unique_origins.is_empty() || unique_destinations.is_empty() {
+            HashMap::new()
+        } else if constraints.is_unconstrained() {
+            self.matrix_astar_distance(
+                &unique_origins,
+                Some(&unique_destinations),
+                false,
+                weight_list_index,
+                infinity,
+                dynamic_infinity,
+                |_: &H3Cell, _: &H3Cell| 0.0,
+            )
+        } else {
+            self.matrix_constrained_distance(
+                &unique_origins,
+                &unique_destinations,
+                false,
+                weight_list_index,
+                infinity,
+                dynamic_infinity,
+                constraints,
+                H3Cell::distance_m,
+            )
+        };
+
+        let mut distances = Vec::with_capacity(origins.len() * destinations.len());
+        let mut status = Vec::with_capacity(origins.len() * destinations.len());
+        for (origin, origin_cell) in origins.iter().zip(origin_cells.iter()) {
+            let row = origin_cell.and_then(|cell| rows.get(&cell)?.as_ref().ok());
+            for (destination, destination_cell) in destinations.iter().zip(destination_cells.iter())
+            {
+                let (Some(origin_cell), Some(destination_cell)) = (origin_cell, destination_cell)
+                else {
+                    distances.push(f64::NAN);
+                    status.push(OdStatus::NotInGraph);
+                    continue;
+                };
+                let distance = row.and_then(|row| row[columns[destination_cell]]);
+                let is_snapped = u64::from(origin_cell.cell) != *origin
+                    || u64::from(destination_cell.cell) != *destination;
+                distances.push(distance.unwrap_or(f64::NAN));
+                status.push(match (distance, is_snapped) {
+                    (None, _) => OdStatus::Unreachable,
+                    (Some(_), true) => OdStatus::Snapped,
+                    (Some(_), false) => OdStatus::Reached,
+                });
+            }
+        }
+
+        OdMatrix {
+            origins,
+            destinations,
+            origin_cells,
+            destination_cells,
+            distances,
+            status,
+        }
+    }
+}
+
+/// origin-destination matrix with numpy accessors
+///
+/// `distances` and `status` are 2d arrays with one row per origin and one column per destination,
+/// the index arrays hold the original ids and the snapped cells (0 if not in the graph)
+#[cfg(feature = "pyo3")]
+#[pyclass(name = "OdMatrix")]
+pub struct PyOdMatrix {
+    pub matrix: OdMatrix,
+}
+
+#[cfg(feature = "pyo3")]
+#[pymethods]
+impl PyOdMatrix {
+    #[getter]
+    pub fn shape(&self) -> (usize, usize) {
+        self.matrix.shape()
+    }
+
+    #[getter]
+    pub fn distances<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+        Array2::from_shape_vec(self.matrix.shape(), self.matrix.distances.clone())
+            .map(|array| array.into_pyarray(py))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+    }
+
+    /// 0: reached, 1: reached after snapping, 2: unreachable, 3: not in graph
+    #[getter]
+    pub fn status<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<u8>> {
+        let status = self.matrix.status.iter().map(|s| *s as u8).collect();
+        Array2::from_shape_vec(self.matrix.shape(), status)
+            .map(|array| array.into_pyarray(py))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+    }
+
+    #[getter]
+    pub fn origins<'py>(&self, py: Python<'py>) -> &'py PyArray1<u64> {
+        PyArray1::from_slice(py, &self.matrix.origins)
+    }
+
+    #[getter]
+    pub fn destinations<'py>(&self, py: Python<'py>) -> &'py PyArray1<u64> {
+        PyArray1::from_slice(py, &self.matrix.destinations)
+    }
+
+    #[getter]
+    pub fn origin_cells<'py>(&self, py: Python<'py>) -> &'py PyArray1<u64> {
+        cells_to_pyarray(py, &self.matrix.origin_cells)
+    }
+
+    #[getter]
+    pub fn destination_cells<'py>(&self, py: Python<'py>) -> &'py PyArray1<u64> {
+        cells_to_pyarray(py, &self.matrix.destination_cells)
+    }
+}
+
+#[cfg(feature = "pyo3")]
+fn cells_to_pyarray<'py>(py: Python<'py>, cells: &[Option<H3Cell>]) -> &'py PyArray1<u64> {
+    let ids = cells
+        .iter()
+        .map(|cell| cell.map_or(0, |cell| u64::from(cell.cell)))
+        .collect::<Vec<_>>();
+    PyArray1::from_vec(py, ids)
+}
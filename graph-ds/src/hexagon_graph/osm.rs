@@ -1,37 +1,122 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use h3o::CellIndex;
-use osmpbf::{Element, ElementReader};
+use h3o::{CellIndex, LatLng, Resolution};
+use osmpbf::{Element, ElementReader, Way};
 
 use super::{OSMOptions, WeightModifier};
 
+pub mod area;
+pub mod elevation;
+pub mod profile;
+
+use elevation::{cell_slope, ElevationRaster};
+use profile::{NodeEffect, OSMProfile, SpeedProfile, WayDirection, WayTags};
+
+/// the tags of barrier, kerb and crossing nodes by node id
+type NodeTags = HashMap<i64, Vec<(String, String)>>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OSMLayer {
     Cycling,
     Walking,
+    Driving,
 }
 
-pub fn calculate_weight(layer: &OSMLayer, options: &WeightModifier, cell_distance: f64) -> f64 {
+/// the travel speed on a way in m/s, `None` if the profile excludes the way
+pub fn way_speed(
+    layer: &OSMLayer,
+    options: &WeightModifier,
+    profile: &SpeedProfile,
+    tags: &WayTags,
+) -> Option<f64> {
     let speed = match layer {
         OSMLayer::Cycling => options.bike_speed,
         OSMLayer::Walking => options.walk_speed,
+        OSMLayer::Driving => profile.road_speed(tags)?,
     };
-    cell_distance / speed / 60.0
+    let multiplier = profile.multiplier(tags);
+    if multiplier <= 0.0 {
+        return None;
+    }
+    Some(speed * multiplier)
 }
 
-impl OSMLayer {
-    pub fn get_default_weight(&self, cell_distance: f64) -> f64 {
-        match self {
-            OSMLayer::Cycling => cell_distance / 4.5 / 60.0,
-            OSMLayer::Walking => cell_distance / 1.4 / 60.0,
+/// the travel time in minutes over `distance_m` meters of a way, `None` if the profile excludes the way
+pub fn calculate_weight(
+    layer: &OSMLayer,
+    options: &WeightModifier,
+    profile: &SpeedProfile,
+    distance_m: f64,
+    tags: &WayTags,
+) -> Option<f64> {
+    Some(distance_m / way_speed(layer, options, profile, tags)? / 60.0)
+}
+
+/// the travel time in minutes between the centers of two cells at `speed` m/s, adjusted to the slope
+/// with `OSMOptions::elevation`
+fn edge_weight(
+    layer: &OSMLayer,
+    options: &OSMOptions,
+    raster: Option<&ElevationRaster>,
+    (from, to): (CellIndex, CellIndex),
+    speed: f64,
+) -> f64 {
+    let distance = LatLng::from(from).distance_m(LatLng::from(to));
+    let weight = distance / speed / 60.0;
+    let (Some(raster), Some(elevation)) = (raster, &options.elevation) else {
+        return weight;
+    };
+    let Some(slope) = cell_slope(raster, from, to, distance) else {
+        return weight;
+    };
+    weight / elevation.model(layer).speed_factor(speed, slope)
+}
+
+/// the cells along a segment between two locations, and whether `grid_path_cells` failed
+///
+/// if there is no grid path, e.g. across a pentagon or for very long segments, the segment is sampled at half
+/// the edge length of the cells instead
+pub(crate) fn segment_cells(
+    from: LatLng,
+    to: LatLng,
+    resolution: Resolution,
+) -> (Vec<CellIndex>, bool) {
+    let path = from
+        .to_cell(resolution)
+        .grid_path_cells(to.to_cell(resolution))
+        .ok()
+        .and_then(|path| path.collect::<Result<Vec<_>, _>>().ok());
+    if let Some(path) = path {
+        return (path, false);
+    }
+
+    let steps = (from.distance_m(to) / (resolution.edge_length_m() / 2.0))
+        .ceil()
+        .max(1.0) as usize;
+    let mut cells: Vec<CellIndex> = Vec::with_capacity(steps + 1);
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let Ok(point) = LatLng::new(
+            from.lat() + t * (to.lat() - from.lat()),
+            from.lng() + t * (to.lng() - from.lng()),
+        ) else {
+            continue;
+        };
+        let cell = point.to_cell(resolution);
+        if cells.last() != Some(&cell) {
+            cells.push(cell);
         }
     }
+    (cells, true)
+}
 
-    /// a way needs to have at least one of the required tags
-    pub fn get_required_tags(&self) -> HashSet<&'static str> {
+impl OSMLayer {
+    /// the profile with the tag rules of the layer
+    pub fn get_profile<'a>(&self, options: &'a OSMOptions) -> &'a OSMProfile {
         match self {
-            OSMLayer::Cycling => HashSet::from(["cycleway", "bicycle", "bicycle_road"]),
-            OSMLayer::Walking => HashSet::from(["highway"]),
+            OSMLayer::Cycling => &options.bike_profile,
+            OSMLayer::Walking => &options.walk_profile,
+            OSMLayer::Driving => &options.drive_profile,
         }
     }
 
@@ -39,136 +124,387 @@ impl OSMLayer {
         match self {
             OSMLayer::Cycling => -2,
             OSMLayer::Walking => -1,
+            OSMLayer::Driving => -3,
         }
     }
 }
 
-/// converts a OSM pbf file into a hexagonal graph layer
-/// * Cycling: 4.5 m/s, layer_idx: 0
-/// * Walking: 1.4 m/s, layer_idx: 1
-#[allow(clippy::type_complexity)]
-pub fn process_osm_pbf(
-    url: &str,
-    options: &OSMOptions,
-    h3_resolution: h3o::Resolution,
-) -> anyhow::Result<Vec<((OSMLayer, CellIndex, CellIndex), f64)>> {
-    let reader = ElementReader::from_path(url)?;
-    let edge_length = h3_resolution.edge_length_m();
-    let cell_distance = (edge_length.powi(2) - (edge_length / 2.0).powi(2)).sqrt() * 2.0;
+/// the result of processing an OSM pbf file
+#[derive(Debug, Default)]
+pub struct OSMData {
+    /// directed edges per layer with their weight in minutes
+    pub edges: Vec<((OSMLayer, CellIndex, CellIndex), f64)>,
+    /// cells where cars can be parked, only filled with the driving layer
+    pub parking: HashSet<CellIndex>,
+    /// cells with bicycle parking, only filled with `OSMOptions::bike_and_ride` and without bike-share
+    pub bike_parking: HashSet<CellIndex>,
+    /// way segments without a grid path between their cells, which were interpolated instead
+    pub path_failures: usize,
+    /// edges left out because a barrier blocks the layer
+    pub blocked_edges: usize,
+    /// edges with the delay of a barrier or crossing
+    pub delayed_edges: usize,
+}
 
-    println!("processing osm pbf file: {url}");
+impl OSMData {
+    fn merge(mut self, other: OSMData) -> OSMData {
+        self.edges.extend(other.edges);
+        self.parking.extend(other.parking);
+        self.bike_parking.extend(other.bike_parking);
+        self.path_failures += other.path_failures;
+        self.blocked_edges += other.blocked_edges;
+        self.delayed_edges += other.delayed_edges;
+        self
+    }
 
-    let edge_data = reader
-        .par_map_reduce(
-            |element| {
-                match element {
-                    Element::Way(way) => {
-                        let layers = if let Some(layer) = options.osm_layer {
-                            vec![layer]
-                        } else {
-                            vec![OSMLayer::Cycling, OSMLayer::Walking]
-                        };
-                        layers
-                            .into_iter()
-                            .flat_map(|layer| {
-                                if way
-                                    .tags()
-                                    .any(|(k, _)| layer.get_required_tags().contains(&k))
-                                    && way.tags().any(|(k, _)| k == "highway")
-                                    && way.tags().all(|(k, v)| tag_value_matches(k, v, &layer))
-                                {
-                                    let node_points = way
-                                        .node_locations()
-                                        .map(|node| {
-                                            h3o::LatLng::new(node.lat(), node.lon())
-                                                .unwrap()
-                                                .to_cell(h3_resolution)
-                                        })
-                                        .collect::<Vec<_>>();
-                                    // for each pair of points, add the points in between
-                                    let path_points = node_points
-                                        .windows(2)
-                                        .flat_map(|cells| {
-                                            let a = cells[0];
-                                            let b = cells[1];
-                                            if let Ok(path) = a.grid_path_cells(b) {
-                                                path.into_iter().flatten().collect()
-                                            } else {
-                                                vec![]
-                                            }
-                                        })
-                                        .collect::<Vec<CellIndex>>();
-                                    let edges = path_points
-                                        .windows(2)
-                                        .flat_map(|cells| {
-                                            let a = cells[0];
-                                            let b = cells[1];
-                                            if a != b {
-                                                Ok((
-                                                    (layer, a, b),
-                                                    calculate_weight(
-                                                        &layer,
-                                                        &options.weight_modifier,
-                                                        cell_distance,
-                                                    ),
-                                                ))
-                                            } else {
-                                                Err(anyhow::anyhow!("same cell"))
-                                            }
-                                        })
-                                        .collect::<Vec<((OSMLayer, CellIndex, CellIndex), f64)>>();
-                                    edges
-                                } else {
-                                    vec![]
-                                }
-                            })
-                            .collect()
-                    }
-                    _ => {
-                        vec![]
-                    }
-                }
-            },
-            Vec::new,
-            |a, b| a.into_iter().chain(b.into_iter()).collect(),
-        )?
-        .into_iter()
-        .collect::<Vec<_>>();
+    /// the parking places of an element, `locations` are the node locations of the element
+    fn from_parking_place(
+        tags: &[(&str, &str)],
+        locations: impl Iterator<Item = (f64, f64)>,
+        driving: bool,
+        bike_and_ride: bool,
+        resolution: Resolution,
+    ) -> OSMData {
+        let mut data = OSMData::default();
+        if driving && is_parking_place(tags, "parking") {
+            // entrances are often next to the mapped location
+            data.parking = parking_cells(locations, resolution, 1);
+        } else if bike_and_ride && is_parking_place(tags, "bicycle_parking") {
+            data.bike_parking = parking_cells(locations, resolution, 0);
+        }
+        data
+    }
+}
 
-    println!("converted OSM file into {:#?} edges", edge_data.len());
+/// statistics of building the OSM layers, and the by-products needed after other layers are merged
+#[derive(Debug, Clone, Default)]
+pub struct OSMBuildReport {
+    /// directed edges per layer
+    pub edges: HashMap<OSMLayer, usize>,
+    /// connections between the driving and the walking layer
+    pub parking_links: usize,
+    /// cells with bicycle parking, see `Graph::<H3Cell>::connect_bike_and_ride`
+    pub bike_parking: HashSet<CellIndex>,
+    /// docking stations connected to the bike-share layer
+    pub bike_share_stations: usize,
+    /// docking stations skipped because of invalid coordinates
+    pub invalid_stations: usize,
+    /// way segments without a grid path between their cells, which were interpolated instead
+    pub path_failures: usize,
+    /// edges left out because a barrier blocks the layer
+    pub blocked_edges: usize,
+    /// edges with the delay of a barrier or crossing
+    pub delayed_edges: usize,
+}
 
-    Ok(edge_data)
+/// the cells of a parking place, with the neighbors within `k` rings
+fn parking_cells(
+    locations: impl Iterator<Item = (f64, f64)>,
+    resolution: Resolution,
+    k: u32,
+) -> HashSet<CellIndex> {
+    locations
+        .filter_map(|(lat, lng)| LatLng::new(lat, lng).ok())
+        .flat_map(|point| point.to_cell(resolution).grid_disk::<Vec<_>>(k))
+        .collect()
 }
 
-pub fn tag_value_matches(tag: &str, value: &str, layer: &OSMLayer) -> bool {
-    match tag {
-        "highway" => !matches!(
-            value,
-            "motorway" | "motorway_link" | "prohibited" | "trunk" | "trunk_link" | "construction"
-        ),
-        "access" => !matches!(value, "private" | "no"),
-        "foot" => {
-            if layer == &OSMLayer::Walking {
-                !matches!(value, "private" | "no")
-            } else {
-                true
-            }
-        }
-        "bicycle" => {
-            if layer == &OSMLayer::Cycling {
-                !matches!(value, "private" | "no" | "none")
+fn is_parking_place(tags: &[(&str, &str)], amenity: &str) -> bool {
+    tags.contains(&("amenity", amenity))
+        && !tags
+            .iter()
+            .any(|(key, value)| *key == "access" && matches!(*value, "private" | "no"))
+}
+
+fn is_barrier_or_crossing(tags: &[(&str, &str)]) -> bool {
+    tags.iter().any(|(key, value)| {
+        matches!(*key, "barrier" | "kerb" | "wheelchair")
+            || (*key == "highway" && matches!(*value, "crossing" | "traffic_signals"))
+    })
+}
+
+/// the tags of all barrier, kerb and crossing nodes, read in a separate pass because the ways only reference their
+/// nodes
+fn read_node_tags(url: &str) -> anyhow::Result<NodeTags> {
+    let owned = |tags: Vec<(&str, &str)>| {
+        tags.into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let nodes = ElementReader::from_path(url)?.par_map_reduce(
+        |element| {
+            let (id, tags) = match element {
+                Element::Node(node) => (node.id(), node.tags().collect::<Vec<_>>()),
+                Element::DenseNode(node) => (node.id(), node.tags().collect::<Vec<_>>()),
+                _ => return NodeTags::new(),
+            };
+            if is_barrier_or_crossing(&tags) {
+                NodeTags::from([(id, owned(tags))])
             } else {
-                true
+                NodeTags::new()
             }
-        }
-        "cycleway" => {
-            if layer == &OSMLayer::Cycling {
-                !matches!(value, "shared" | "no" | "none")
-            } else {
-                true
+        },
+        NodeTags::new,
+        |mut a, b| {
+            a.extend(b);
+            a
+        },
+    )?;
+    Ok(nodes)
+}
+
+/// the combined effect of the barrier and crossing nodes of a way per cell
+fn cell_effects(
+    way: &Way,
+    node_points: &[LatLng],
+    profile: &OSMProfile,
+    highway: Option<&str>,
+    nodes: &NodeTags,
+    resolution: Resolution,
+) -> HashMap<CellIndex, NodeEffect> {
+    let mut effects: HashMap<CellIndex, NodeEffect> = HashMap::new();
+    if nodes.is_empty() || profile.nodes.is_empty() {
+        return effects;
+    }
+    for (id, point) in way.refs().zip(node_points) {
+        let Some(tags) = nodes.get(&id) else {
+            continue;
+        };
+        let tags = tags
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let effect = effects
+            .entry(point.to_cell(resolution))
+            .or_insert(NodeEffect::Pass);
+        *effect = effect.combine(profile.nodes.effect(&tags, highway));
+    }
+    effects
+}
+
+/// the directed edges of one way on one layer, and the cells where cars can park along it
+///
+/// barrier and crossing nodes of the way act on the edges entering their cell, a blocked cell can not be
+/// entered from this way and a delay is added to the weight
+fn way_edges(
+    way: &Way,
+    layer: OSMLayer,
+    options: &OSMOptions,
+    raster: Option<&ElevationRaster>,
+    nodes: &NodeTags,
+) -> OSMData {
+    let profile = layer.get_profile(options);
+    let all_tags = way.tags().collect::<Vec<_>>();
+    if !profile.accepts(&all_tags) {
+        return OSMData::default();
+    }
+    let direction = profile.oneway.direction(&all_tags);
+    let tags = WayTags::from_tags(way.tags());
+    let Some(speed) = way_speed(&layer, &options.weight_modifier, &profile.speed, &tags) else {
+        return OSMData::default();
+    };
+
+    let node_points = way
+        .node_locations()
+        .map(|node| LatLng::new(node.lat(), node.lon()).unwrap())
+        .collect::<Vec<_>>();
+    // for each pair of points, add the points in between
+    let mut path_failures = 0;
+    let path_points = node_points
+        .windows(2)
+        .flat_map(|points| {
+            let (cells, failed) = segment_cells(points[0], points[1], options.resolution);
+            path_failures += usize::from(failed);
+            cells
+        })
+        .collect::<Vec<CellIndex>>();
+
+    let effects = cell_effects(
+        way,
+        &node_points,
+        profile,
+        tags.highway.as_deref(),
+        nodes,
+        options.resolution,
+    );
+    let mut edges = Vec::new();
+    let (mut blocked_edges, mut delayed_edges) = (0, 0);
+    for cells in path_points.windows(2).filter(|cells| cells[0] != cells[1]) {
+        let (a, b) = (cells[0], cells[1]);
+        let pairs = match direction {
+            WayDirection::Both => vec![(a, b), (b, a)],
+            WayDirection::Forward => vec![(a, b)],
+            WayDirection::Backward => vec![(b, a)],
+        };
+        for pair in pairs {
+            let weight = edge_weight(&layer, options, raster, pair, speed);
+            match effects.get(&pair.1) {
+                Some(NodeEffect::Blocked) => blocked_edges += 1,
+                Some(NodeEffect::Delay(delay)) => {
+                    delayed_edges += 1;
+                    edges.push(((layer, pair.0, pair.1), weight + delay));
+                }
+                _ => edges.push(((layer, pair.0, pair.1), weight)),
             }
         }
-        "bycicle_road" => layer == &OSMLayer::Cycling,
-        _ => true,
     }
+
+    let parking = if layer == OSMLayer::Driving && profile.parking.allows_parking(&all_tags) {
+        path_points.into_iter().collect()
+    } else {
+        HashSet::new()
+    };
+    OSMData {
+        edges,
+        parking,
+        path_failures,
+        blocked_edges,
+        delayed_edges,
+        ..Default::default()
+    }
+}
+
+/// fills a walkable area with cells and connects every cell to its neighbors in both directions
+fn area_edges(
+    way: &Way,
+    layer: OSMLayer,
+    options: &OSMOptions,
+    raster: Option<&ElevationRaster>,
+) -> OSMData {
+    let profile = layer.get_profile(options);
+    let refs = way.refs().collect::<Vec<_>>();
+    if refs.len() < 4 || refs.first() != refs.last() {
+        return OSMData::default();
+    }
+    if !profile.accepts_area(&way.tags().collect::<Vec<_>>()) {
+        return OSMData::default();
+    }
+    let tags = WayTags::from_tags(way.tags());
+    let Some(speed) = way_speed(&layer, &options.weight_modifier, &profile.speed, &tags) else {
+        return OSMData::default();
+    };
+
+    let ring = way
+        .node_locations()
+        .filter_map(|node| LatLng::new(node.lat(), node.lon()).ok())
+        .collect::<Vec<_>>();
+    let cells = area::fill_ring(&ring, options.resolution);
+    let edges = cells
+        .iter()
+        .flat_map(|cell| {
+            cell.grid_disk::<Vec<_>>(1)
+                .into_iter()
+                .filter(|neighbor| neighbor != cell && cells.contains(neighbor))
+                .map(|neighbor| {
+                    let pair = (*cell, neighbor);
+                    let weight = edge_weight(&layer, options, raster, pair, speed);
+                    ((layer, pair.0, pair.1), weight)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    OSMData {
+        edges,
+        ..Default::default()
+    }
+}
+
+/// converts a OSM pbf file into a hexagonal graph layer
+/// * Walking: `walk_speed` of the weight options, layer_idx: -1
+/// * Cycling: `bike_speed` of the weight options, layer_idx: -2
+/// * Driving: `maxspeed` or the speed of the highway class in the profile, layer_idx: -3
+///
+/// the ways are selected and their speeds scaled by the profile of the layer. The edges are directed,
+/// a bidirectional way yields an edge in both directions. The weights follow the great circle distance between
+/// the cell centers. With `OSMOptions::elevation`, the weights of both
+/// directions follow the slope between the cells. Areas such as squares and parks are filled with cells that
+/// are connected to all their neighbors. Barrier and crossing nodes close edges or delay them as set in the
+/// `nodes` rules of the profiles, which needs an extra pass over the file
+pub fn process_osm_pbf(url: &str, options: &OSMOptions) -> anyhow::Result<OSMData> {
+    let reader = ElementReader::from_path(url)?;
+
+    let raster = options
+        .elevation
+        .as_ref()
+        .map(|elevation| ElevationRaster::from_geotiff(&elevation.dem_path))
+        .transpose()?;
+    let layers = options.osm_layers();
+    let driving = layers.contains(&OSMLayer::Driving);
+    let bike_and_ride = options.bike_and_ride_enabled() && layers.contains(&OSMLayer::Cycling);
+    let is_parking = |tags: &[(&str, &str)]| {
+        (driving && is_parking_place(tags, "parking"))
+            || (bike_and_ride && is_parking_place(tags, "bicycle_parking"))
+    };
+
+    println!("processing osm pbf file: {url}");
+
+    let nodes = if layers
+        .iter()
+        .any(|layer| !layer.get_profile(options).nodes.is_empty())
+    {
+        read_node_tags(url)?
+    } else {
+        NodeTags::new()
+    };
+
+    let osm_data = reader.par_map_reduce(
+        |element| match element {
+            Element::Way(way) => {
+                let data = layers
+                    .iter()
+                    .flat_map(|layer| {
+                        [
+                            way_edges(&way, *layer, options, raster.as_ref(), &nodes),
+                            area_edges(&way, *layer, options, raster.as_ref()),
+                        ]
+                    })
+                    .fold(OSMData::default(), OSMData::merge);
+                let tags = way.tags().collect::<Vec<_>>();
+                if is_parking(&tags) {
+                    data.merge(OSMData::from_parking_place(
+                        &tags,
+                        way.node_locations().map(|node| (node.lat(), node.lon())),
+                        driving,
+                        bike_and_ride,
+                        options.resolution,
+                    ))
+                } else {
+                    data
+                }
+            }
+            Element::Node(node) if is_parking(&node.tags().collect::<Vec<_>>()) => {
+                OSMData::from_parking_place(
+                    &node.tags().collect::<Vec<_>>(),
+                    [(node.lat(), node.lon())].into_iter(),
+                    driving,
+                    bike_and_ride,
+                    options.resolution,
+                )
+            }
+            Element::DenseNode(node) if is_parking(&node.tags().collect::<Vec<_>>()) => {
+                OSMData::from_parking_place(
+                    &node.tags().collect::<Vec<_>>(),
+                    [(node.lat(), node.lon())].into_iter(),
+                    driving,
+                    bike_and_ride,
+                    options.resolution,
+                )
+            }
+            _ => OSMData::default(),
+        },
+        OSMData::default,
+        OSMData::merge,
+    )?;
+
+    println!(
+        "converted OSM file into {:#?} edges, interpolated {} segments without grid path",
+        osm_data.edges.len(),
+        osm_data.path_failures
+    );
+    println!(
+        "barriers blocked {} edges, barriers and crossings delayed {} edges",
+        osm_data.blocked_edges, osm_data.delayed_edges
+    );
+
+    Ok(osm_data)
 }
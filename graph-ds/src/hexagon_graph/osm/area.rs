@@ -0,0 +1,37 @@
+use std::collections::HashSet;
+
+use h3o::{CellIndex, LatLng, Resolution};
+
+use super::segment_cells;
+use crate::hexagon_graph::spatial_index::{point_in_ring, BoundingBox};
+
+/// the cells whose center is inside the ring, plus the cells along the ring so that ways ending at the
+/// outline are connected
+pub fn fill_ring(ring: &[LatLng], resolution: Resolution) -> HashSet<CellIndex> {
+    let Some(bbox) = BoundingBox::from_points(ring) else {
+        return HashSet::new();
+    };
+    let mut cells = ring
+        .windows(2)
+        .flat_map(|points| segment_cells(points[0], points[1], resolution).0)
+        .collect::<HashSet<_>>();
+
+    let Ok(center) = LatLng::new(
+        (bbox.min_lat + bbox.max_lat) / 2.0,
+        (bbox.min_lng + bbox.max_lng) / 2.0,
+    ) else {
+        return cells;
+    };
+    let corner = LatLng::new(bbox.max_lat, bbox.max_lng).unwrap_or(center);
+    // the distance between the centers of neighboring cells is √3 times the edge length
+    let cell_distance = resolution.edge_length_m() * 3f64.sqrt();
+    let k = (center.distance_m(corner) / cell_distance).ceil() as u32 + 1;
+    cells.extend(
+        center
+            .to_cell(resolution)
+            .grid_disk::<Vec<_>>(k)
+            .into_iter()
+            .filter(|cell| point_in_ring(&LatLng::from(*cell), ring)),
+    );
+    cells
+}
@@ -0,0 +1,280 @@
+use std::{fs::File, io::BufReader, path::Path, str::FromStr};
+
+use h3o::{CellIndex, LatLng};
+use tiff::{
+    decoder::{Decoder, DecodingResult, Limits},
+    tags::Tag,
+};
+
+use super::OSMLayer;
+
+const GRAVITY: f64 = 9.81;
+const AIR_DENSITY: f64 = 1.225;
+
+/// GeoTIFF key of the model type, 1 is projected, 2 is geographic and 3 is geocentric
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+
+/// a single band elevation raster in geographic coordinates (EPSG:4326), heights in meters
+#[derive(Debug, Clone)]
+pub struct ElevationRaster {
+    width: usize,
+    height: usize,
+    /// longitude and latitude of the upper left corner of the upper left pixel
+    origin: (f64, f64),
+    /// pixel size in degrees, longitude and latitude
+    pixel_size: (f64, f64),
+    data: Vec<f64>,
+    nodata: Option<f64>,
+}
+
+impl ElevationRaster {
+    /// reads a GeoTIFF DEM, the georeference is taken from the model tie point and pixel scale tags
+    ///
+    /// the raster needs geographic coordinates, projected rasters (e.g. UTM) have to be reprojected first
+    pub fn from_geotiff(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = BufReader::new(File::open(path.as_ref())?);
+        let mut decoder = Decoder::new(file)?.with_limits(Limits::unlimited());
+        let (width, height) = decoder.dimensions()?;
+
+        let geo_keys = decoder
+            .get_tag_u16_vec(Tag::GeoKeyDirectoryTag)
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "missing GeoTIFF key directory in {}",
+                    path.as_ref().display()
+                )
+            })?;
+        match geo_key(&geo_keys, GT_MODEL_TYPE_GEO_KEY) {
+            Some(MODEL_TYPE_GEOGRAPHIC) => {}
+            model_type => {
+                return Err(anyhow::anyhow!(
+                    "only geographic (EPSG:4326) elevation rasters are supported, {} has model type {:?}",
+                    path.as_ref().display(),
+                    model_type
+                ))
+            }
+        }
+
+        let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag)?;
+        let tie_point = decoder.get_tag_f64_vec(Tag::ModelTiepointTag)?;
+        let (Some(&[scale_x, scale_y]), Some(&[i, j, _, x, y])) =
+            (scale.get(..2), tie_point.get(..5))
+        else {
+            return Err(anyhow::anyhow!(
+                "invalid GeoTIFF georeference in {}",
+                path.as_ref().display()
+            ));
+        };
+        let nodata = decoder
+            .get_tag_ascii_string(Tag::GdalNodata)
+            .ok()
+            .and_then(|nodata| nodata.trim_matches(char::from(0)).trim().parse().ok());
+
+        let data = match decoder.read_image()? {
+            DecodingResult::U8(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::U16(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::U32(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::U64(data) => data.into_iter().map(|v| v as f64).collect(),
+            DecodingResult::F32(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::F64(data) => data,
+            DecodingResult::I8(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::I16(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::I32(data) => data.into_iter().map(f64::from).collect(),
+            DecodingResult::I64(data) => data.into_iter().map(|v| v as f64).collect(),
+        };
+        if data.len() != width as usize * height as usize {
+            return Err(anyhow::anyhow!(
+                "only single band elevation rasters are supported"
+            ));
+        }
+
+        Ok(ElevationRaster {
+            width: width as usize,
+            height: height as usize,
+            origin: (x - i * scale_x, y + j * scale_y),
+            pixel_size: (scale_x, scale_y),
+            data,
+            nodata,
+        })
+    }
+
+    fn value(&self, column: usize, row: usize) -> Option<f64> {
+        let value = self.data[row * self.width + column];
+        if value.is_nan() || self.nodata.is_some_and(|nodata| value == nodata) {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// bilinear interpolation between the pixel centers, `None` outside the raster or on missing data
+    pub fn height_at(&self, point: LatLng) -> Option<f64> {
+        let column = (point.lng() - self.origin.0) / self.pixel_size.0 - 0.5;
+        let row = (self.origin.1 - point.lat()) / self.pixel_size.1 - 0.5;
+        if column < -0.5
+            || row < -0.5
+            || column > self.width as f64 - 0.5
+            || row > self.height as f64 - 0.5
+        {
+            return None;
+        }
+        let clamp = |value: f64, size: usize| value.clamp(0.0, (size - 1) as f64);
+        let (column, row) = (clamp(column, self.width), clamp(row, self.height));
+        let (c0, r0) = (column.floor() as usize, row.floor() as usize);
+        let (c1, r1) = ((c0 + 1).min(self.width - 1), (r0 + 1).min(self.height - 1));
+        let (dc, dr) = (column - c0 as f64, row - r0 as f64);
+
+        let top = self.value(c0, r0)? * (1.0 - dc) + self.value(c1, r0)? * dc;
+        let bottom = self.value(c0, r1)? * (1.0 - dc) + self.value(c1, r1)? * dc;
+        Some(top * (1.0 - dr) + bottom * dr)
+    }
+}
+
+/// the value of a key in a GeoTIFF key directory, `None` for missing keys and keys stored in other tags
+///
+/// the directory starts with a header of four values, the last being the number of keys, followed by
+/// an entry of four values per key: id, tag location, count and value
+fn geo_key(directory: &[u16], key: u16) -> Option<u16> {
+    let nr_keys = *directory.get(3)? as usize;
+    directory
+        .get(4..)?
+        .chunks_exact(4)
+        .take(nr_keys)
+        .find(|entry| entry[0] == key && entry[1] == 0)
+        .map(|entry| entry[3])
+}
+
+/// parameters of the power based cycling model, the rider keeps the power needed for the base speed on flat
+/// ground and pushes harder on climbs
+#[derive(Debug, Clone, Copy)]
+pub struct CyclingPower {
+    /// rider and bike
+    pub mass_kg: f64,
+    pub rolling_resistance: f64,
+    /// drag coefficient times frontal area
+    pub drag_area_m2: f64,
+    /// the power the rider puts in on climbs to hold the base speed, in watts
+    pub climb_power_w: f64,
+    /// speed limit when going downhill, in m/s
+    pub max_speed: f64,
+    /// on steep climbs the bike is pushed, in m/s
+    pub min_speed: f64,
+}
+
+impl Default for CyclingPower {
+    fn default() -> Self {
+        CyclingPower {
+            mass_kg: 85.0,
+            rolling_resistance: 0.005,
+            drag_area_m2: 0.5,
+            climb_power_w: 120.0,
+            max_speed: 9.0,
+            min_speed: 1.0,
+        }
+    }
+}
+
+impl CyclingPower {
+    fn power(&self, speed: f64, slope: f64) -> f64 {
+        let angle = slope.atan();
+        speed * self.mass_kg * GRAVITY * (angle.sin() + self.rolling_resistance * angle.cos())
+            + 0.5 * AIR_DENSITY * self.drag_area_m2 * speed.powi(3)
+    }
+
+    /// the speed on the slope with the power of the base speed, found by bisection
+    fn speed(&self, base_speed: f64, slope: f64) -> f64 {
+        // on climbs the rider tries to hold the base speed, up to the climb power
+        let power = self
+            .power(base_speed, 0.0)
+            .max(self.power(base_speed, slope).min(self.climb_power_w));
+        if self.power(self.max_speed, slope) <= power {
+            return self.max_speed;
+        }
+        let (mut low, mut high) = (0.0, self.max_speed);
+        for _ in 0..50 {
+            let speed = (low + high) / 2.0;
+            if self.power(speed, slope) < power {
+                low = speed;
+            } else {
+                high = speed;
+            }
+        }
+        low.max(self.min_speed)
+    }
+}
+
+/// how the travel speed changes with the slope of an edge
+#[derive(Debug, Clone, Copy)]
+pub enum SlopeModel {
+    /// the speed does not depend on the slope
+    Flat,
+    /// Tobler's hiking function, scaled so the speed on flat ground stays the base speed
+    Tobler,
+    Power(CyclingPower),
+}
+
+impl FromStr for SlopeModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(SlopeModel::Flat),
+            "tobler" => Ok(SlopeModel::Tobler),
+            "power" => Ok(SlopeModel::Power(CyclingPower::default())),
+            other => Err(anyhow::anyhow!(
+                "unknown slope model {other}, expected flat, tobler or power"
+            )),
+        }
+    }
+}
+
+impl SlopeModel {
+    /// the factor the base speed in m/s is multiplied with on a slope (rise over run, positive is uphill)
+    pub fn speed_factor(&self, base_speed: f64, slope: f64) -> f64 {
+        match self {
+            SlopeModel::Flat => 1.0,
+            SlopeModel::Tobler => (-3.5 * ((slope + 0.05).abs() - 0.05)).exp(),
+            SlopeModel::Power(power) => power.speed(base_speed, slope) / base_speed,
+        }
+    }
+}
+
+/// an elevation raster and the slope models of the walking and cycling layers, driving ignores the slope
+#[derive(Debug, Clone)]
+pub struct ElevationOptions {
+    /// path to a GeoTIFF DEM in geographic coordinates
+    pub dem_path: String,
+    pub walk_model: SlopeModel,
+    pub bike_model: SlopeModel,
+}
+
+impl ElevationOptions {
+    pub fn new(dem_path: &str) -> Self {
+        ElevationOptions {
+            dem_path: dem_path.to_string(),
+            walk_model: SlopeModel::Tobler,
+            bike_model: SlopeModel::Power(CyclingPower::default()),
+        }
+    }
+
+    pub fn model(&self, layer: &OSMLayer) -> SlopeModel {
+        match layer {
+            OSMLayer::Cycling => self.bike_model,
+            OSMLayer::Walking => self.walk_model,
+            OSMLayer::Driving => SlopeModel::Flat,
+        }
+    }
+}
+
+/// the slope between the centers of two neighboring cells, rise over run
+pub fn cell_slope(
+    raster: &ElevationRaster,
+    from: CellIndex,
+    to: CellIndex,
+    cell_distance: f64,
+) -> Option<f64> {
+    let from_height = raster.height_at(LatLng::from(from))?;
+    let to_height = raster.height_at(LatLng::from(to))?;
+    Some((to_height - from_height) / cell_distance)
+}
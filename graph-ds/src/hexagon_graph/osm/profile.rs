@@ -0,0 +1,401 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// the tags of a way that influence the travel speed along it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct WayTags {
+    pub highway: Option<String>,
+    pub surface: Option<String>,
+    pub smoothness: Option<String>,
+    pub maxspeed: Option<String>,
+}
+
+impl WayTags {
+    pub fn from_tags<'a>(tags: impl Iterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut way_tags = WayTags::default();
+        for (key, value) in tags {
+            match key {
+                "highway" => way_tags.highway = Some(value.to_string()),
+                "surface" => way_tags.surface = Some(value.to_string()),
+                "smoothness" => way_tags.smoothness = Some(value.to_string()),
+                "maxspeed" => way_tags.maxspeed = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        way_tags
+    }
+
+    pub fn is_steps(&self) -> bool {
+        self.highway.as_deref() == Some("steps")
+    }
+}
+
+/// speed multipliers per tag value, values without an entry keep the full speed
+///
+/// the multipliers of `highway`, `surface` and `smoothness` are multiplied. Steps use the `steps` multiplier
+/// instead of the `highway` one. A multiplier of 0 makes the way impassable.
+///
+/// layers without a configured speed (driving) take the speed of a way from its `maxspeed` tag or from
+/// `highway_speeds` in km/h
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedProfile {
+    pub highway: HashMap<String, f64>,
+    pub surface: HashMap<String, f64>,
+    pub smoothness: HashMap<String, f64>,
+    pub steps: f64,
+    pub highway_speeds: HashMap<String, f64>,
+}
+
+impl Default for SpeedProfile {
+    fn default() -> Self {
+        SpeedProfile {
+            highway: HashMap::new(),
+            surface: HashMap::new(),
+            smoothness: HashMap::new(),
+            steps: 1.0,
+            highway_speeds: HashMap::new(),
+        }
+    }
+}
+
+impl SpeedProfile {
+    /// the factor the base speed is multiplied with on a way with these tags
+    pub fn multiplier(&self, tags: &WayTags) -> f64 {
+        let lookup = |table: &HashMap<String, f64>, value: &Option<String>| {
+            value
+                .as_ref()
+                .and_then(|value| table.get(value))
+                .copied()
+                .unwrap_or(1.0)
+        };
+        let highway = if tags.is_steps() {
+            self.steps
+        } else {
+            lookup(&self.highway, &tags.highway)
+        };
+        highway * lookup(&self.surface, &tags.surface) * lookup(&self.smoothness, &tags.smoothness)
+    }
+
+    /// the speed limit of a way in m/s, `None` if it has neither a known maxspeed nor a highway class speed
+    pub fn road_speed(&self, tags: &WayTags) -> Option<f64> {
+        let kmh = tags
+            .maxspeed
+            .as_deref()
+            .and_then(parse_maxspeed)
+            .or_else(|| {
+                tags.highway
+                    .as_ref()
+                    .and_then(|highway| self.highway_speeds.get(highway))
+                    .copied()
+            })?;
+        Some(kmh / 3.6)
+    }
+}
+
+/// parses a `maxspeed` value into km/h, e.g. `50`, `30 mph`, `walk` or zone values such as `DK:urban`
+pub fn parse_maxspeed(value: &str) -> Option<f64> {
+    // multiple values are separated by `;`, the first one is used
+    let value = value.split(';').next()?.trim();
+    if let Some(mph) = value.strip_suffix("mph") {
+        return mph.trim().parse::<f64>().ok().map(|mph| mph * 1.609);
+    }
+    if let Ok(kmh) = value.trim_end_matches("km/h").trim().parse::<f64>() {
+        return Some(kmh);
+    }
+    match value.rsplit(':').next()? {
+        "walk" => Some(6.0),
+        "living_street" => Some(10.0),
+        "zone30" => Some(30.0),
+        "urban" => Some(50.0),
+        "rural" | "trunk" => Some(80.0),
+        "motorway" => Some(130.0),
+        _ => None,
+    }
+}
+
+/// the directions a way can be travelled in, relative to the order of its nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WayDirection {
+    Both,
+    Forward,
+    Backward,
+}
+
+/// one-way rules of a layer, ways are bidirectional if no rule applies
+///
+/// * the first of `tags` present on a way sets its direction (`yes`, `true`, `1` forward, `-1`, `reverse`
+///   backward, anything else both)
+/// * without such a tag, a `junction` value in `junctions` makes the way one-way forward
+/// * a one-way way stays bidirectional if it has one of the `contraflow` tag values
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OnewayRules {
+    pub tags: Vec<String>,
+    pub junctions: Vec<String>,
+    pub contraflow: HashMap<String, Vec<String>>,
+}
+
+impl OnewayRules {
+    pub fn direction(&self, tags: &[(&str, &str)]) -> WayDirection {
+        let value_of = |key: &str| tags.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+        let direction = if let Some(value) = self.tags.iter().find_map(|key| value_of(key)) {
+            match value {
+                "yes" | "true" | "1" => WayDirection::Forward,
+                "-1" | "reverse" => WayDirection::Backward,
+                _ => WayDirection::Both,
+            }
+        } else if value_of("junction")
+            .is_some_and(|junction| self.junctions.iter().any(|j| j == junction))
+        {
+            WayDirection::Forward
+        } else {
+            WayDirection::Both
+        };
+        let has_contraflow = tags.iter().any(|(key, value)| {
+            self.contraflow
+                .get(*key)
+                .is_some_and(|values| values.iter().any(|v| v == value))
+        });
+        if has_contraflow {
+            WayDirection::Both
+        } else {
+            direction
+        }
+    }
+}
+
+/// where cars can be parked along the ways of a layer, `amenity=parking` is always a parking place
+///
+/// street parking is allowed on ways of the `highways` classes, unless one of the `forbidden` tag values is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParkingRules {
+    pub highways: Vec<String>,
+    pub forbidden: HashMap<String, Vec<String>>,
+}
+
+impl ParkingRules {
+    pub fn allows_parking(&self, tags: &[(&str, &str)]) -> bool {
+        tags.iter().any(|(key, value)| {
+            *key == "highway" && self.highways.iter().any(|highway| highway == value)
+        }) && !tags.iter().any(|(key, value)| {
+            self.forbidden
+                .get(*key)
+                .is_some_and(|forbidden| forbidden.iter().any(|v| v == value))
+        })
+    }
+}
+
+/// the effect of a barrier or crossing node on the edges entering its cell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeEffect {
+    Pass,
+    /// delay in minutes
+    Delay(f64),
+    Blocked,
+}
+
+impl NodeEffect {
+    /// combines the effects of several nodes in the same cell
+    pub fn combine(self, other: NodeEffect) -> NodeEffect {
+        match (self, other) {
+            (NodeEffect::Blocked, _) | (_, NodeEffect::Blocked) => NodeEffect::Blocked,
+            (NodeEffect::Pass, effect) | (effect, NodeEffect::Pass) => effect,
+            (NodeEffect::Delay(a), NodeEffect::Delay(b)) => NodeEffect::Delay(a + b),
+        }
+    }
+}
+
+/// how barrier (`barrier=*`), kerb (`kerb=*`) and crossing (`highway=crossing`, `highway=traffic_signals`) nodes
+/// on the ways of a layer affect the edges entering their cell
+///
+/// * a node blocks the layer if the first of `access_tags` present on it is `no` or `private`
+/// * a barrier blocks the layer if its value is in `blocking_barriers` and no access tag allows the layer. Other
+///   barriers are delayed by `barrier_delays`
+/// * a kerb blocks the layer if its `kerb` value is in `blocking_kerbs` and no access tag allows the layer,
+///   `barrier=kerb` without a `kerb` value counts as `kerb=yes`
+/// * crossings are delayed by `crossing_delays` per `crossing` value, `traffic_signals` is also used for
+///   `highway=traffic_signals`. Only ways of the `crossing_highways` classes are delayed, so the crossed road is
+///   not, all ways if it is empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeRules {
+    pub blocking_barriers: Vec<String>,
+    pub blocking_kerbs: Vec<String>,
+    pub access_tags: Vec<String>,
+    pub barrier_delays: HashMap<String, f64>,
+    pub crossing_delays: HashMap<String, f64>,
+    pub crossing_highways: Vec<String>,
+}
+
+impl NodeRules {
+    /// the effect of a node with `node_tags` on a way of the `way_highway` class
+    pub fn effect(&self, node_tags: &[(&str, &str)], way_highway: Option<&str>) -> NodeEffect {
+        let value_of = |key: &str| node_tags.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+
+        let access = self.access_tags.iter().find_map(|key| value_of(key));
+        if access.is_some_and(|access| matches!(access, "no" | "private")) {
+            return NodeEffect::Blocked;
+        }
+        let allowed = access.is_some_and(|access| {
+            matches!(
+                access,
+                "yes" | "designated" | "permissive" | "destination" | "customers"
+            )
+        });
+        let kerb = value_of("kerb").or((value_of("barrier") == Some("kerb")).then_some("yes"));
+        if !allowed && kerb.is_some_and(|kerb| self.blocking_kerbs.iter().any(|k| k == kerb)) {
+            return NodeEffect::Blocked;
+        }
+
+        let barrier = value_of("barrier").map_or(NodeEffect::Pass, |barrier| {
+            if !allowed && self.blocking_barriers.iter().any(|b| b == barrier) {
+                NodeEffect::Blocked
+            } else {
+                self.barrier_delays
+                    .get(barrier)
+                    .map_or(NodeEffect::Pass, |delay| NodeEffect::Delay(*delay))
+            }
+        });
+
+        let crossing_way = self.crossing_highways.is_empty()
+            || way_highway
+                .is_some_and(|highway| self.crossing_highways.iter().any(|h| h == highway));
+        let crossing = match value_of("highway") {
+            Some("crossing") if crossing_way => value_of("crossing").unwrap_or("unmarked"),
+            Some("traffic_signals") if crossing_way => "traffic_signals",
+            _ => return barrier,
+        };
+        barrier.combine(
+            self.crossing_delays
+                .get(crossing)
+                .map_or(NodeEffect::Pass, |delay| NodeEffect::Delay(*delay)),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocking_barriers.is_empty()
+            && self.blocking_kerbs.is_empty()
+            && self.access_tags.is_empty()
+            && self.barrier_delays.is_empty()
+            && self.crossing_delays.is_empty()
+    }
+}
+
+/// closed ways that are filled with cells and connected internally, e.g. pedestrian plazas and parks
+///
+/// a closed way is an area if it has one of the `always` tag values, or one of the `with_area_tag` tag values
+/// together with `area=yes`. Multipolygon relations are not supported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AreaRules {
+    pub always: HashMap<String, Vec<String>>,
+    pub with_area_tag: HashMap<String, Vec<String>>,
+}
+
+impl AreaRules {
+    pub fn matches(&self, tags: &[(&str, &str)]) -> bool {
+        let has_value = |rules: &HashMap<String, Vec<String>>| {
+            tags.iter().any(|(key, value)| {
+                rules
+                    .get(*key)
+                    .is_some_and(|values| values.iter().any(|v| v == value))
+            })
+        };
+        has_value(&self.always)
+            || (tags.contains(&("area", "yes")) && has_value(&self.with_area_tag))
+    }
+}
+
+/// the tag rules of an OSM layer, which ways are included and how fast they are
+///
+/// profiles can be loaded from TOML or JSON files, see `profiles/` for the built-in profiles.
+/// A way is included if
+/// * it has at least one of the `required_tags`, if any are given
+/// * it has all of the `mandatory_tags`
+/// * none of its tags has a value listed in `excluded_values`, the value `"*"` excludes any value
+///
+/// `oneway` decides in which directions the included ways can be travelled, `parking` where cars can be
+/// parked, `areas` which closed ways are filled with cells and `nodes` how barriers and crossings on the ways
+/// affect them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OSMProfile {
+    pub name: String,
+    pub required_tags: Vec<String>,
+    pub mandatory_tags: Vec<String>,
+    pub excluded_values: HashMap<String, Vec<String>>,
+    pub speed: SpeedProfile,
+    pub oneway: OnewayRules,
+    pub parking: ParkingRules,
+    pub areas: AreaRules,
+    pub nodes: NodeRules,
+}
+
+impl OSMProfile {
+    pub fn walking() -> Self {
+        Self::from_toml(include_str!("../../../profiles/walk.toml"))
+            .expect("the built-in walk profile is valid")
+    }
+
+    pub fn cycling() -> Self {
+        Self::from_toml(include_str!("../../../profiles/bike.toml"))
+            .expect("the built-in bike profile is valid")
+    }
+
+    /// the step-free walking profile for wheelchair users, an alternative to `walking`
+    pub fn wheelchair() -> Self {
+        Self::from_toml(include_str!("../../../profiles/wheelchair.toml"))
+            .expect("the built-in wheelchair profile is valid")
+    }
+
+    pub fn driving() -> Self {
+        Self::from_toml(include_str!("../../../profiles/drive.toml"))
+            .expect("the built-in drive profile is valid")
+    }
+
+    pub fn from_toml(profile: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(profile)?)
+    }
+
+    pub fn from_json(profile: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(profile)?)
+    }
+
+    /// loads a profile file, the format is chosen by the extension (`.toml` or `.json`)
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let profile = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&profile),
+            Some("json") => Self::from_json(&profile),
+            _ => Err(anyhow::anyhow!(
+                "unknown profile format of {}, expected .toml or .json",
+                path.display()
+            )),
+        }
+    }
+
+    /// checks the inclusion and exclusion rules against all tags of a way
+    pub fn accepts(&self, tags: &[(&str, &str)]) -> bool {
+        let has_tag = |key: &String| tags.iter().any(|(k, _)| k == key);
+        (self.required_tags.is_empty() || self.required_tags.iter().any(has_tag))
+            && self.mandatory_tags.iter().all(has_tag)
+            && !self.is_excluded(tags)
+    }
+
+    pub fn is_excluded(&self, tags: &[(&str, &str)]) -> bool {
+        tags.iter().any(|(key, value)| {
+            self.excluded_values
+                .get(*key)
+                .is_some_and(|excluded| excluded.iter().any(|v| v == "*" || v == value))
+        })
+    }
+
+    /// checks if a closed way is an area that is filled with cells, it must not have excluded tag values
+    pub fn accepts_area(&self, tags: &[(&str, &str)]) -> bool {
+        self.areas.matches(tags) && !self.is_excluded(tags)
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use h3o::{CellIndex, LatLng, Resolution};
+use osmpbf::{Element, ElementReader, Way};
+use serde::{Deserialize, Serialize};
+
+use super::osm::area::fill_ring;
+
+/// points of interest per category, a cell appears once for every feature of the category in it
+pub type PoiCells = HashMap<String, Vec<CellIndex>>;
+
+/// the categories of points of interest and the tag values that belong to them
+///
+/// categories can be loaded from TOML or JSON files, see `profiles/pois.toml` for the built-in categories. A
+/// feature belongs to a category if one of its tags has a value listed for the category, the value `"*"` matches
+/// any value. With `fill_areas`, closed ways are filled with the cells inside them, otherwise they are reduced to
+/// the centroid of their nodes like open ways.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoiCategories {
+    pub categories: HashMap<String, HashMap<String, Vec<String>>>,
+    pub fill_areas: bool,
+}
+
+impl PoiCategories {
+    pub fn built_in() -> Self {
+        Self::from_toml(include_str!("../../profiles/pois.toml"))
+            .expect("the built-in POI categories are valid")
+    }
+
+    pub fn from_toml(categories: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(categories)?)
+    }
+
+    pub fn from_json(categories: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(categories)?)
+    }
+
+    /// loads a categories file, the format is chosen by the extension (`.toml` or `.json`)
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let categories = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&categories),
+            Some("json") => Self::from_json(&categories),
+            _ => Err(anyhow::anyhow!(
+                "unknown POI categories format of {}, expected .toml or .json",
+                path.display()
+            )),
+        }
+    }
+
+    /// the categories a feature with `tags` belongs to
+    pub fn classify(&self, tags: &[(&str, &str)]) -> Vec<&str> {
+        self.categories
+            .iter()
+            .filter(|(_, rules)| {
+                tags.iter().any(|(key, value)| {
+                    rules
+                        .get(*key)
+                        .is_some_and(|values| values.iter().any(|v| v == "*" || v == value))
+                })
+            })
+            .map(|(category, _)| category.as_str())
+            .collect()
+    }
+}
+
+/// the mean of the node locations of a way, the closing node of a ring is counted once
+fn centroid(points: &[LatLng]) -> Option<LatLng> {
+    let points = match points {
+        [first, rest @ ..] if rest.last() == Some(first) => rest,
+        points => points,
+    };
+    if points.is_empty() {
+        return None;
+    }
+    let count = points.len() as f64;
+    LatLng::new(
+        points.iter().map(|point| point.lat()).sum::<f64>() / count,
+        points.iter().map(|point| point.lng()).sum::<f64>() / count,
+    )
+    .ok()
+}
+
+/// the cells of a way, areas smaller than a cell fall back to the centroid
+fn way_cells(way: &Way, fill_areas: bool, resolution: Resolution) -> HashSet<CellIndex> {
+    let points = way
+        .node_locations()
+        .filter_map(|node| LatLng::new(node.lat(), node.lon()).ok())
+        .collect::<Vec<_>>();
+    let refs = way.refs().collect::<Vec<_>>();
+    if fill_areas && refs.len() >= 4 && refs.first() == refs.last() {
+        let cells = fill_ring(&points, resolution);
+        if !cells.is_empty() {
+            return cells;
+        }
+    }
+    centroid(&points)
+        .map(|point| point.to_cell(resolution))
+        .into_iter()
+        .collect()
+}
+
+fn feature_cells(
+    categories: &[&str],
+    cells: impl IntoIterator<Item = CellIndex> + Clone,
+) -> PoiCells {
+    categories
+        .iter()
+        .map(|category| (category.to_string(), cells.clone().into_iter().collect()))
+        .collect()
+}
+
+fn merge(mut a: PoiCells, b: PoiCells) -> PoiCells {
+    for (category, cells) in b {
+        a.entry(category).or_default().extend(cells);
+    }
+    a
+}
+
+/// reads the points of interest of an OSM pbf file into H3 cells at `resolution`, in a single pass
+///
+/// nodes are placed in their cell and ways in their centroid or area cells, see `PoiCategories`. Ways need
+/// the node locations in the file. Multipolygon relations are not included.
+pub fn process_pois(
+    url: &str,
+    categories: &PoiCategories,
+    resolution: Resolution,
+) -> anyhow::Result<PoiCells> {
+    println!("reading points of interest from {url}");
+
+    let node_cells = |tags: Vec<(&str, &str)>, lat: f64, lon: f64| {
+        let matches = categories.classify(&tags);
+        match LatLng::new(lat, lon) {
+            Ok(point) if !matches.is_empty() => {
+                feature_cells(&matches, [point.to_cell(resolution)])
+            }
+            _ => PoiCells::new(),
+        }
+    };
+    let mut pois = ElementReader::from_path(url)?.par_map_reduce(
+        |element| match element {
+            Element::Node(node) => node_cells(node.tags().collect(), node.lat(), node.lon()),
+            Element::DenseNode(node) => node_cells(node.tags().collect(), node.lat(), node.lon()),
+            Element::Way(way) => {
+                let matches = categories.classify(&way.tags().collect::<Vec<_>>());
+                if matches.is_empty() {
+                    return PoiCells::new();
+                }
+                feature_cells(&matches, way_cells(&way, categories.fill_areas, resolution))
+            }
+            Element::Relation(_) => PoiCells::new(),
+        },
+        PoiCells::new,
+        merge,
+    )?;
+
+    for (category, cells) in pois.iter_mut() {
+        cells.sort_unstable();
+        println!("{category}: {} cells", cells.len());
+    }
+    Ok(pois)
+}
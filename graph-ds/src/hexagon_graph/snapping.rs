@@ -0,0 +1,217 @@
+use h3o::{CellIndex, LatLng, Resolution};
+use rayon::prelude::*;
+
+use crate::Graph;
+
+use super::h3cell::H3Cell;
+
+/// the search stops after this many rings around the input, even if no node was found
+pub(crate) const MAX_RINGS: u32 = 1000;
+
+/// a location that should be snapped to a node of the graph
+#[derive(Debug, Clone, Copy)]
+pub enum SnapTarget {
+    Point(LatLng),
+    /// a H3 cell at any resolution, finer cells are mapped to their parent and coarser cells to their center
+    /// child at the resolution of the graph. The distance is measured from the center of the input cell.
+    Cell(CellIndex),
+}
+
+impl From<LatLng> for SnapTarget {
+    fn from(point: LatLng) -> Self {
+        SnapTarget::Point(point)
+    }
+}
+
+impl From<CellIndex> for SnapTarget {
+    fn from(cell: CellIndex) -> Self {
+        SnapTarget::Cell(cell)
+    }
+}
+
+impl SnapTarget {
+    fn lat_lng(&self) -> LatLng {
+        match self {
+            SnapTarget::Point(point) => *point,
+            SnapTarget::Cell(cell) => LatLng::from(*cell),
+        }
+    }
+
+    /// the cell at `resolution` that contains the target
+    fn cell_at(&self, resolution: Resolution) -> CellIndex {
+        match self {
+            SnapTarget::Point(point) => point.to_cell(resolution),
+            SnapTarget::Cell(cell) if cell.resolution() > resolution => {
+                cell.parent(resolution).unwrap_or(*cell)
+            }
+            SnapTarget::Cell(cell) => cell.center_child(resolution).unwrap_or(*cell),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapOptions {
+    /// candidates whose center is further away than this are ignored
+    pub max_distance_m: f64,
+    /// only nodes of this layer are candidates
+    pub layer: i16,
+    /// only nodes of the largest weakly connected component are candidates
+    pub largest_component_only: bool,
+}
+
+impl Default for SnapOptions {
+    fn default() -> Self {
+        SnapOptions {
+            max_distance_m: 50.0,
+            layer: -1,
+            largest_component_only: false,
+        }
+    }
+}
+
+impl SnapOptions {
+    /// a radius that covers `k_ring` rings of cells at the given resolution
+    pub fn from_k_ring(k_ring: u32, resolution: Resolution) -> Self {
+        SnapOptions {
+            max_distance_m: k_ring as f64 * resolution.edge_length_m() * 3f64.sqrt(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Snapped {
+    pub cell: H3Cell,
+    /// distance between the input and the center of the node in meters
+    pub distance_m: f64,
+}
+
+impl Graph<H3Cell> {
+    /// the resolution of the cells in the graph, None for an empty graph
+    pub fn resolution(&self) -> Option<Resolution> {
+        let nodes = self.nodes.as_ref().read().unwrap();
+        nodes
+            .iter()
+            .flatten()
+            .next()
+            .map(|node| node.id.cell.resolution())
+    }
+
+    /// maps every target to the closest node of the graph, returns None for targets without a node in reach
+    ///
+    /// the search walks outward ring by ring around the cell of the target and stops once no closer node
+    /// can follow. With `largest_component_only`, the components are computed once per call, so targets
+    /// should be snapped in batches.
+    pub fn snap(&self, targets: &[SnapTarget], options: &SnapOptions) -> Vec<Option<Snapped>> {
+        let Some(resolution) = self.resolution() else {
+            return vec![None; targets.len()];
+        };
+        let components = if options.largest_component_only {
+            Some(self.weakly_connected_components())
+        } else {
+            None
+        };
+        let node_map = self.node_map.as_ref().read().unwrap();
+
+        let is_candidate = |cell: CellIndex| {
+            let candidate = H3Cell {
+                cell,
+                layer: options.layer,
+            };
+            match (node_map.get_by_left(&candidate), &components) {
+                (Some(idx), Some(components)) => components[*idx] == Some(0),
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        };
+
+        targets
+            .par_iter()
+            .map(|target| {
+                let point = target.lat_lng();
+                let center = target.cell_at(resolution);
+
+                let ring_lower_bound = ring_lower_bound(center, 0.0);
+
+                let mut best: Option<Snapped> = None;
+                for k in 0..=MAX_RINGS {
+                    let reach = best.map_or(options.max_distance_m, |best| {
+                        best.distance_m.min(options.max_distance_m)
+                    });
+                    if ring_lower_bound(k) > reach {
+                        break;
+                    }
+                    for cell in grid_ring(center, k) {
+                        if !is_candidate(cell) {
+                            continue;
+                        }
+                        let distance_m = point.distance_m(LatLng::from(cell));
+                        if distance_m <= options.max_distance_m
+                            && distance_m < best.map_or(f64::INFINITY, |best| best.distance_m)
+                        {
+                            best = Some(Snapped {
+                                cell: H3Cell {
+                                    cell,
+                                    layer: options.layer,
+                                },
+                                distance_m,
+                            });
+                        }
+                    }
+                }
+                best
+            })
+            .collect()
+    }
+
+    /// snaps H3 cell ids at any resolution, ids that are not valid cells are not snapped
+    pub fn snap_ids(&self, ids: &[u64], options: &SnapOptions) -> Vec<Option<Snapped>> {
+        let cells = ids
+            .iter()
+            .map(|id| CellIndex::try_from(*id).ok())
+            .collect::<Vec<_>>();
+        let targets = cells
+            .iter()
+            .flatten()
+            .map(|cell| SnapTarget::Cell(*cell))
+            .collect::<Vec<_>>();
+        let mut snapped = self.snap(&targets, options).into_iter();
+        cells
+            .iter()
+            .map(|cell| cell.and_then(|_| snapped.next().flatten()))
+            .collect()
+    }
+}
+
+/// lower bound of the distance between a point inside `center` and any cell in ring k around it
+///
+/// a cell in ring k is at least k * spacing * sqrt(3) / 2 away from the center cell, and the point is at most
+/// one circumradius away from it. Both get some slack for the distortion of the grid, `overshoot` is
+/// subtracted as well for contents that may reach beyond their cell.
+pub(crate) fn ring_lower_bound(center: CellIndex, overshoot: f64) -> impl Fn(u32) -> f64 {
+    let spacing = center
+        .grid_disk::<Vec<_>>(1)
+        .into_iter()
+        .find(|neighbor| *neighbor != center)
+        .map_or(
+            center.resolution().edge_length_m() * 3f64.sqrt(),
+            |neighbor| LatLng::from(center).distance_m(LatLng::from(neighbor)),
+        );
+    let circumradius = spacing / 3f64.sqrt();
+    move |k: u32| k as f64 * spacing * 0.9 * 3f64.sqrt() / 2.0 - circumradius * 1.1 - overshoot
+}
+
+/// the cells at exactly grid distance `k`, also around pentagons
+pub(crate) fn grid_ring(center: CellIndex, k: u32) -> Vec<CellIndex> {
+    if k == 0 {
+        return vec![center];
+    }
+    if let Some(ring) = center.grid_ring_fast(k).collect::<Option<Vec<_>>>() {
+        return ring;
+    }
+    center
+        .grid_disk_distances_safe(k)
+        .filter(|(_, distance)| *distance == k)
+        .map(|(cell, _)| cell)
+        .collect()
+}
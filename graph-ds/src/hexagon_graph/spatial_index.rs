@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use h3o::{CellIndex, LatLng, Resolution};
+
+use crate::Graph;
+
+use super::{
+    h3cell::H3Cell,
+    snapping::{grid_ring, ring_lower_bound, MAX_RINGS},
+};
+
+/// the buckets are this many resolutions coarser than the nodes, so one bucket holds up to 7^3 nodes
+const BUCKET_LEVELS: u8 = 3;
+
+/// bounding box in degrees, boxes crossing the antimeridian are not supported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_lng: f64,
+    pub max_lat: f64,
+    pub max_lng: f64,
+}
+
+impl BoundingBox {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a LatLng>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bbox = BoundingBox {
+            min_lat: first.lat(),
+            min_lng: first.lng(),
+            max_lat: first.lat(),
+            max_lng: first.lng(),
+        };
+        points.for_each(|point| bbox.extend(point));
+        Some(bbox)
+    }
+
+    pub fn extend(&mut self, point: &LatLng) {
+        self.min_lat = self.min_lat.min(point.lat());
+        self.min_lng = self.min_lng.min(point.lng());
+        self.max_lat = self.max_lat.max(point.lat());
+        self.max_lng = self.max_lng.max(point.lng());
+    }
+
+    pub fn contains(&self, point: &LatLng) -> bool {
+        (self.min_lat..=self.max_lat).contains(&point.lat())
+            && (self.min_lng..=self.max_lng).contains(&point.lng())
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_lat <= other.max_lat
+            && other.min_lat <= self.max_lat
+            && self.min_lng <= other.max_lng
+            && other.min_lng <= self.max_lng
+    }
+}
+
+/// even-odd ray casting test of a point against a ring of coordinates, the ring may be open or closed
+pub fn point_in_ring(point: &LatLng, ring: &[LatLng]) -> bool {
+    let (x, y) = (point.lng(), point.lat());
+    let mut inside = false;
+    for (i, a) in ring.iter().enumerate() {
+        let b = &ring[(i + 1) % ring.len()];
+        if (a.lat() > y) != (b.lat() > y)
+            && x < (b.lng() - a.lng()) * (y - a.lat()) / (b.lat() - a.lat()) + a.lng()
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// a point is inside a polygon if it is inside the exterior ring and outside all holes
+pub fn point_in_polygon(point: &LatLng, exterior: &[LatLng], holes: &[Vec<LatLng>]) -> bool {
+    point_in_ring(point, exterior) && !holes.iter().any(|hole| point_in_ring(point, hole))
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    bbox: BoundingBox,
+    cells: Vec<(H3Cell, LatLng)>,
+}
+
+/// spatial index over the nodes of one layer, the nodes are bucketed by a coarser H3 parent cell
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    bucket_resolution: Resolution,
+    buckets: HashMap<CellIndex, Bucket>,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        SpatialIndex {
+            bucket_resolution: Resolution::Zero,
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl SpatialIndex {
+    /// all cells have to be of the same resolution
+    pub fn new(cells: impl IntoIterator<Item = H3Cell>) -> Self {
+        let mut cells = cells.into_iter().peekable();
+        let Some(first) = cells.peek() else {
+            return SpatialIndex::default();
+        };
+        let bucket_resolution =
+            Resolution::try_from(u8::from(first.cell.resolution()).saturating_sub(BUCKET_LEVELS))
+                .unwrap_or(Resolution::Zero);
+
+        let mut buckets: HashMap<CellIndex, Bucket> = HashMap::new();
+        for cell in cells {
+            let Some(parent) = cell.cell.parent(bucket_resolution) else {
+                continue;
+            };
+            let center = LatLng::from(cell.cell);
+            let bucket = buckets.entry(parent).or_insert_with(|| Bucket {
+                bbox: BoundingBox::from_points([&center]).unwrap(),
+                cells: Vec::new(),
+            });
+            bucket.bbox.extend(&center);
+            bucket.cells.push((cell, center));
+        }
+        SpatialIndex {
+            bucket_resolution,
+            buckets,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.values().map(|bucket| bucket.cells.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// the node closest to `point` with its distance in meters, searching bucket rings by increasing distance
+    ///
+    /// the search stops after `MAX_RINGS` rings of buckets, or once every bucket was visited
+    pub fn nearest(&self, point: &LatLng, max_distance_m: f64) -> Option<(H3Cell, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let center = point.to_cell(self.bucket_resolution);
+        // the children of a bucket may reach a bit beyond the bucket boundary
+        let overshoot = self.bucket_resolution.edge_length_m();
+        let lower_bound = ring_lower_bound(center, overshoot);
+
+        let mut best: Option<(H3Cell, f64)> = None;
+        let mut visited = 0;
+        for k in 0..=MAX_RINGS {
+            let reach = best.map_or(max_distance_m, |(_, distance)| distance.min(max_distance_m));
+            if lower_bound(k) > reach || visited == self.buckets.len() {
+                break;
+            }
+            for bucket in grid_ring(center, k)
+                .iter()
+                .filter_map(|parent| self.buckets.get(parent))
+            {
+                visited += 1;
+                for (cell, center) in bucket.cells.iter() {
+                    let distance = point.distance_m(*center);
+                    if distance <= max_distance_m
+                        && distance < best.map_or(f64::INFINITY, |(_, best)| best)
+                    {
+                        best = Some((*cell, distance));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// all nodes whose center is within `radius_m` of `point`, with their distance in meters
+    pub fn within(&self, point: &LatLng, radius_m: f64) -> Vec<(H3Cell, f64)> {
+        // meters per degree of latitude, longitude degrees get shorter towards the poles
+        let lat_delta = radius_m / 111_320.0;
+        let lng_delta = lat_delta / point.lat().to_radians().cos().max(1e-6);
+        let bbox = BoundingBox {
+            min_lat: point.lat() - lat_delta,
+            min_lng: point.lng() - lng_delta,
+            max_lat: point.lat() + lat_delta,
+            max_lng: point.lng() + lng_delta,
+        };
+        self.buckets
+            .values()
+            .filter(|bucket| bucket.bbox.intersects(&bbox))
+            .flat_map(|bucket| bucket.cells.iter())
+            .filter(|(_, center)| bbox.contains(center))
+            .map(|(cell, center)| (*cell, point.distance_m(*center)))
+            .filter(|(_, distance)| *distance <= radius_m)
+            .collect()
+    }
+
+    /// all nodes whose center is inside the bounding box
+    pub fn in_bbox(&self, bbox: &BoundingBox) -> Vec<H3Cell> {
+        self.buckets
+            .values()
+            .filter(|bucket| bucket.bbox.intersects(bbox))
+            .flat_map(|bucket| bucket.cells.iter())
+            .filter(|(_, center)| bbox.contains(center))
+            .map(|(cell, _)| *cell)
+            .collect()
+    }
+
+    /// all nodes whose center is inside the polygon
+    pub fn in_polygon(&self, exterior: &[LatLng], holes: &[Vec<LatLng>]) -> Vec<H3Cell> {
+        let Some(bbox) = BoundingBox::from_points(exterior) else {
+            return vec![];
+        };
+        self.buckets
+            .values()
+            .filter(|bucket| bucket.bbox.intersects(&bbox))
+            .flat_map(|bucket| bucket.cells.iter())
+            .filter(|(_, center)| {
+                bbox.contains(center) && point_in_polygon(center, exterior, holes)
+            })
+            .map(|(cell, _)| *cell)
+            .collect()
+    }
+}
+
+impl Graph<H3Cell> {
+    /// builds a spatial index over the nodes of a layer, usually the base layer -1
+    pub fn spatial_index(&self, layer: i16) -> SpatialIndex {
+        let nodes = self.nodes.as_ref().read().unwrap();
+        SpatialIndex::new(
+            nodes
+                .iter()
+                .flatten()
+                .map(|node| node.id)
+                .filter(|cell| cell.layer == layer),
+        )
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use h3o::{CellIndex, LatLng};
+use serde_json::Value;
+
+use crate::Graph;
+
+use super::{h3cell::H3Cell, spatial_index::SpatialIndex};
+
+/// a polygon as its exterior ring and its holes
+pub type Polygon = (Vec<LatLng>, Vec<Vec<LatLng>>);
+
+/// selects the nodes that are kept by `Graph::<H3Cell>::subgraph`
+///
+/// * with `polygons` or `parent_cells`, a node is kept if its center is inside any polygon or its cell has
+///   one of the parent cells
+/// * with `layers`, only nodes of these layers are kept
+#[derive(Debug, Clone, Default)]
+pub struct SubgraphFilter {
+    pub polygons: Vec<Polygon>,
+    /// parent cells at any resolution
+    pub parent_cells: HashSet<CellIndex>,
+    pub layers: Option<HashSet<i16>>,
+}
+
+impl SubgraphFilter {
+    /// adds the polygons of a GeoJSON geometry, feature or feature collection
+    pub fn with_geojson(mut self, geojson: &str) -> anyhow::Result<Self> {
+        let value: Value = serde_json::from_str(geojson)?;
+        self.polygons.extend(polygons_from_geojson(&value)?);
+        Ok(self)
+    }
+
+    fn is_spatial(&self) -> bool {
+        !self.polygons.is_empty() || !self.parent_cells.is_empty()
+    }
+}
+
+pub(crate) fn polygons_from_geojson(value: &Value) -> anyhow::Result<Vec<Polygon>> {
+    let geometry_type = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("GeoJSON object without a type"))?;
+    match geometry_type {
+        "FeatureCollection" => value
+            .get("features")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow::anyhow!("feature collection without features"))?
+            .iter()
+            .map(polygons_from_geojson)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map(|polygons| polygons.into_iter().flatten().collect()),
+        "Feature" => value
+            .get("geometry")
+            .filter(|geometry| !geometry.is_null())
+            .map_or(Ok(vec![]), polygons_from_geojson),
+        "Polygon" => Ok(vec![polygon_from_coordinates(
+            value.get("coordinates").unwrap_or(&Value::Null),
+        )?]),
+        "MultiPolygon" => value
+            .get("coordinates")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow::anyhow!("multipolygon without coordinates"))?
+            .iter()
+            .map(polygon_from_coordinates)
+            .collect(),
+        other => Err(anyhow::anyhow!("unsupported GeoJSON type {other}")),
+    }
+}
+
+/// GeoJSON polygon coordinates are rings of [lng, lat] positions, the first ring is the exterior
+fn polygon_from_coordinates(coordinates: &Value) -> anyhow::Result<Polygon> {
+    let mut rings = coordinates
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("polygon without coordinates"))?
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .ok_or_else(|| anyhow::anyhow!("polygon ring is not an array"))?
+                .iter()
+                .map(|position| {
+                    let (Some(lng), Some(lat)) = (
+                        position.get(0).and_then(Value::as_f64),
+                        position.get(1).and_then(Value::as_f64),
+                    ) else {
+                        return Err(anyhow::anyhow!("invalid position {position}"));
+                    };
+                    Ok(LatLng::new(lat, lng)?)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .into_iter();
+    let exterior = rings
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("polygon without exterior ring"))?;
+    Ok((exterior, rings.collect()))
+}
+
+impl Graph<H3Cell> {
+    /// creates a new graph with the nodes selected by `filter`
+    ///
+    /// only edges whose endpoints are both kept are copied, together with their weight lists.
+    /// Nodes without any remaining edge are dropped.
+    pub fn subgraph(&self, filter: &SubgraphFilter) -> anyhow::Result<Graph<H3Cell>> {
+        let in_polygons = if filter.polygons.is_empty() {
+            HashSet::new()
+        } else {
+            let nodes = self.nodes.as_ref().read().unwrap();
+            let index = SpatialIndex::new(nodes.iter().flatten().map(|node| node.id));
+            filter
+                .polygons
+                .iter()
+                .flat_map(|(exterior, holes)| index.in_polygon(exterior, holes))
+                .collect::<HashSet<_>>()
+        };
+        let parent_resolutions = filter
+            .parent_cells
+            .iter()
+            .map(|cell| cell.resolution())
+            .collect::<HashSet<_>>();
+
+        let keeps = |cell: &H3Cell| {
+            if let Some(layers) = &filter.layers {
+                if !layers.contains(&cell.layer) {
+                    return false;
+                }
+            }
+            !filter.is_spatial()
+                || in_polygons.contains(cell)
+                || parent_resolutions.iter().any(|resolution| {
+                    cell.cell
+                        .parent(*resolution)
+                        .is_some_and(|parent| filter.parent_cells.contains(&parent))
+                })
+        };
+
+        let kept = {
+            let nodes = self.nodes.as_ref().read().unwrap();
+            nodes
+                .iter()
+                .map(|node| node.as_ref().is_some_and(|node| keeps(&node.id)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut subgraph = Graph::<H3Cell>::new();
+        let nodes = self.nodes.as_ref().read().unwrap();
+        let edges = self.edges.as_ref().read().unwrap();
+        for edge in edges.values().flatten() {
+            if !kept[edge.from] || !kept[edge.to] {
+                continue;
+            }
+            let (Some(Some(from)), Some(Some(to))) = (nodes.get(edge.from), nodes.get(edge.to))
+            else {
+                continue;
+            };
+            subgraph.build_and_add_egde(
+                from.id,
+                to.id,
+                edge.weight,
+                edge.weight_list.clone(),
+                edge.capacity,
+            )?;
+        }
+        Ok(subgraph)
+    }
+}
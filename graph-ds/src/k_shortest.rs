@@ -0,0 +1,232 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::{DijkstraNode, Edge, Graph};
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
+    /// calculates up to `k` loopless paths between two nodes with Yen's algorithm, returns the paths and their costs
+    ///
+    /// the paths are distinct and sorted by cost, the first one is the shortest path
+    pub fn k_shortest_paths(
+        &self,
+        start: &T,
+        end: &T,
+        k: usize,
+        weight_list_index: Option<usize>,
+    ) -> anyhow::Result<Vec<(Vec<T>, f64)>> {
+        let node_map_access = self.node_map.as_ref().read().unwrap();
+        let node_list_access = self.nodes.as_ref().read().unwrap();
+        let edges_access = self.edges.as_ref().read().unwrap();
+
+        let start_idx = *node_map_access
+            .get_by_left(start)
+            .ok_or_else(|| anyhow::anyhow!("start node {start:?} not found in node map"))?;
+        let end_idx = *node_map_access
+            .get_by_left(end)
+            .ok_or_else(|| anyhow::anyhow!("end node {end:?} not found in node map"))?;
+
+        let path_cost = |path: &[usize]| -> f64 {
+            path.windows(2)
+                .map(|pair| {
+                    edges_access
+                        .get(&pair[0])
+                        .and_then(|edges| edges.get(&Edge::new(pair[0], pair[1], None, None)))
+                        .map_or(f64::INFINITY, |edge| edge.weight_at(weight_list_index))
+                })
+                .sum()
+        };
+
+        let mut shortest: Vec<(Vec<usize>, f64)> = Vec::new();
+        if k == 0 {
+            return Ok(vec![]);
+        }
+        let Some(first) = restricted_dijkstra(
+            &edges_access,
+            start_idx,
+            end_idx,
+            &HashSet::new(),
+            &HashSet::new(),
+            weight_list_index,
+        ) else {
+            return Err(anyhow::anyhow!("no path found"));
+        };
+        shortest.push(first);
+
+        let mut candidates: Vec<(Vec<usize>, f64)> = Vec::new();
+        while shortest.len() < k {
+            let (previous, _) = shortest.last().unwrap().clone();
+
+            for spur_pos in 0..previous.len() - 1 {
+                let spur_idx = previous[spur_pos];
+                let root = &previous[..=spur_pos];
+
+                // remove the edges that continue a known path with the same root
+                let banned_edges = shortest
+                    .iter()
+                    .map(|(path, _)| path)
+                    .filter(|path| path.len() > spur_pos + 1 && &path[..=spur_pos] == root)
+                    .map(|path| (path[spur_pos], path[spur_pos + 1]))
+                    .collect::<HashSet<_>>();
+                // the root may not be visited again to keep the path loopless
+                let banned_nodes = root[..spur_pos].iter().cloned().collect::<HashSet<_>>();
+
+                if let Some((spur_path, _)) = restricted_dijkstra(
+                    &edges_access,
+                    spur_idx,
+                    end_idx,
+                    &banned_nodes,
+                    &banned_edges,
+                    weight_list_index,
+                ) {
+                    let path = root[..spur_pos]
+                        .iter()
+                        .chain(spur_path.iter())
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if !candidates.iter().any(|(candidate, _)| candidate == &path) {
+                        let cost = path_cost(&path);
+                        candidates.push((path, cost));
+                    }
+                }
+            }
+
+            let Some((best_idx, _)) = candidates
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.1.partial_cmp(&b.1).unwrap())
+            else {
+                break;
+            };
+            shortest.push(candidates.swap_remove(best_idx));
+        }
+
+        Ok(shortest
+            .into_iter()
+            .map(|(path, cost)| {
+                (
+                    path.into_iter()
+                        .filter_map(|idx| node_list_access[idx].as_ref().map(|node| node.id))
+                        .collect(),
+                    cost,
+                )
+            })
+            .collect())
+    }
+}
+
+/// Dijkstra search on node indices that ignores the banned nodes and directed edges
+fn restricted_dijkstra(
+    edges_access: &HashMap<usize, HashSet<Edge>, nohash::BuildNoHashHasher<usize>>,
+    start_idx: usize,
+    end_idx: usize,
+    banned_nodes: &HashSet<usize>,
+    banned_edges: &HashSet<(usize, usize)>,
+    weight_list_index: Option<usize>,
+) -> Option<(Vec<usize>, f64)> {
+    let mut q: BinaryHeap<Reverse<DijkstraNode>> = BinaryHeap::new();
+    let mut distances: HashMap<usize, f64> = HashMap::new();
+    let mut parents: HashMap<usize, usize> = HashMap::new();
+
+    distances.insert(start_idx, 0.0);
+    q.push(Reverse(DijkstraNode {
+        id: start_idx,
+        distance: 0.0,
+    }));
+
+    while let Some(Reverse(current)) = q.pop() {
+        if current.distance > distances[&current.id] {
+            continue;
+        }
+        if current.id == end_idx {
+            let mut path = vec![end_idx];
+            while let Some(parent) = parents.get(path.last().unwrap()) {
+                path.push(*parent);
+            }
+            path.reverse();
+            return Some((path, current.distance));
+        }
+
+        let Some(next_edges) = edges_access.get(&current.id) else {
+            continue;
+        };
+        for next_edge in next_edges.iter() {
+            if banned_nodes.contains(&next_edge.to)
+                || banned_edges.contains(&(current.id, next_edge.to))
+            {
+                continue;
+            }
+            let distance = current.distance + next_edge.weight_at(weight_list_index);
+            if distance < *distances.get(&next_edge.to).unwrap_or(&f64::INFINITY) {
+                distances.insert(next_edge.to, distance);
+                parents.insert(next_edge.to, current.id);
+                q.push(Reverse(DijkstraNode {
+                    id: next_edge.to,
+                    distance,
+                }));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::hexagon_graph::h3cell::H3Cell;
+    use crate::test_graph::{astar_distance, cell, graph};
+    use crate::Graph;
+
+    /// four loopless paths from 0 to 4, costing 3, 3.5, 4 and 4.5. The edges between 1 and 2 form a cycle
+    fn network() -> Graph<H3Cell> {
+        let walk = |idx| cell(idx, -1);
+        graph(&[
+            (walk(0), walk(1), 1.0),
+            (walk(0), walk(2), 2.0),
+            (walk(1), walk(2), 0.5),
+            (walk(2), walk(1), 0.5),
+            (walk(1), walk(3), 1.0),
+            (walk(2), walk(3), 1.0),
+            (walk(3), walk(4), 1.0),
+        ])
+    }
+
+    #[test]
+    fn finds_all_loopless_paths_in_order() {
+        let graph = network();
+        let paths = graph
+            .k_shortest_paths(&cell(0, -1), &cell(4, -1), 10, None)
+            .unwrap();
+        let costs = paths.iter().map(|(_, cost)| *cost).collect::<Vec<_>>();
+        assert_eq!(costs, vec![3.0, 3.5, 4.0, 4.5]);
+        assert_eq!(
+            Some(costs[0]),
+            astar_distance(&graph, cell(0, -1), cell(4, -1))
+        );
+
+        let distinct = paths.iter().map(|(path, _)| path).collect::<HashSet<_>>();
+        assert_eq!(distinct.len(), paths.len());
+        for (path, _) in paths.iter() {
+            let nodes = path.iter().collect::<HashSet<_>>();
+            assert_eq!(nodes.len(), path.len(), "{path:?} has a loop");
+            assert_eq!(path.first(), Some(&cell(0, -1)));
+            assert_eq!(path.last(), Some(&cell(4, -1)));
+        }
+    }
+
+    #[test]
+    fn returns_at_most_k_paths() {
+        let graph = network();
+        let paths = graph
+            .k_shortest_paths(&cell(0, -1), &cell(4, -1), 2, None)
+            .unwrap();
+        assert_eq!(
+            paths.iter().map(|(_, cost)| *cost).collect::<Vec<_>>(),
+            vec![3.0, 3.5]
+        );
+        assert!(graph
+            .k_shortest_paths(&cell(4, -1), &cell(0, -1), 2, None)
+            .is_err());
+    }
+}
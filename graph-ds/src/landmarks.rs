@@ -0,0 +1,448 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{DijkstraNode, Edge, Graph};
+
+/// relative amount the landmark bounds are lowered by to absorb floating point rounding
+const BOUND_SLACK: f64 = 1e-9;
+
+/// how landmarks are placed in the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LandmarkStrategy {
+    /// each new landmark is the node farthest away from all previous landmarks
+    Farthest,
+    /// each new landmark is placed in the region of a shortest path tree where the current bounds are weakest
+    Avoid,
+}
+
+/// precomputed distances between landmarks and all nodes for the ALT (A*, landmarks, triangle inequality) heuristic
+///
+/// the distances are calculated with the static edge weights. Time-dependent weight lists never undercut the
+/// static weight of an edge, so the bounds stay admissible for them as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LandmarkTable {
+    /// `Graph::graph_hash` of the graph the table was built for, the table is only valid for a graph with the
+    /// same nodes, edges and weights
+    pub graph_hash: u64,
+    /// node indices of the landmarks
+    pub landmarks: Vec<usize>,
+    /// `from_landmark[l][v]` is the distance from landmark `l` to node `v`
+    pub from_landmark: Vec<Vec<f64>>,
+    /// `to_landmark[l][v]` is the distance from node `v` to landmark `l`
+    pub to_landmark: Vec<Vec<f64>>,
+}
+
+/// the landmark bounds towards a set of targets, the bound is the distance to the nearest target
+#[derive(Debug, Clone)]
+pub struct TargetBounds {
+    /// minimum distance from each landmark to any target
+    min_from_landmark: Vec<f64>,
+    /// maximum distance from any target to each landmark
+    max_to_landmark: Vec<f64>,
+}
+
+impl LandmarkTable {
+    /// lower bound of the distance between two node indices
+    pub fn lower_bound(&self, from: usize, to: usize) -> f64 {
+        self.from_landmark
+            .iter()
+            .zip(self.to_landmark.iter())
+            .map(|(from_landmark, to_landmark)| {
+                // d(l, to) <= d(l, from) + d(from, to) and d(from, l) <= d(from, to) + d(to, l)
+                let forward = round_down(from_landmark[to], from_landmark[from]);
+                let backward = round_down(to_landmark[from], to_landmark[to]);
+                [forward, backward]
+                    .into_iter()
+                    .filter(|bound| bound.is_finite())
+                    .fold(0.0, f64::max)
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// prepares the lower bounds towards the nearest of several targets
+    pub fn target_bounds(&self, targets: &[usize]) -> TargetBounds {
+        TargetBounds {
+            min_from_landmark: self
+                .from_landmark
+                .iter()
+                .map(|distances| {
+                    targets
+                        .iter()
+                        .map(|t| distances[*t])
+                        .fold(f64::INFINITY, f64::min)
+                })
+                .collect(),
+            max_to_landmark: self
+                .to_landmark
+                .iter()
+                .map(|distances| {
+                    targets
+                        .iter()
+                        .map(|t| distances[*t])
+                        .fold(f64::NEG_INFINITY, f64::max)
+                })
+                .collect(),
+        }
+    }
+
+    /// lower bound of the distance from a node index to the nearest target
+    pub fn lower_bound_to_targets(&self, from: usize, targets: &TargetBounds) -> f64 {
+        (0..self.landmarks.len())
+            .map(|l| {
+                let forward = round_down(targets.min_from_landmark[l], self.from_landmark[l][from]);
+                let backward = round_down(self.to_landmark[l][from], targets.max_to_landmark[l]);
+                [forward, backward]
+                    .into_iter()
+                    .filter(|bound| bound.is_finite())
+                    .fold(0.0, f64::max)
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
+    /// hash of the node IDs in the order of their indices and of every edge with its static weight
+    ///
+    /// graphs with the same cells but other weights, e.g. built with other speeds or profiles, get another hash
+    pub fn graph_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.nodes
+            .as_ref()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|node| node.as_ref().map(|node| node.id))
+            .for_each(|id| id.hash(&mut hasher));
+        let mut edges = self
+            .edges
+            .as_ref()
+            .read()
+            .unwrap()
+            .values()
+            .flatten()
+            .map(|edge| (edge.from, edge.to, edge.weight.map(f64::to_bits)))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// selects `count` landmarks and stores their distance tables in the graph,
+    /// `astar` and `matrix_astar_distance` use them from then on
+    ///
+    /// adding nodes or edges and removing edges drops the tables, they have to be built again afterwards
+    /// the landmarks are placed in the largest weakly connected component
+    pub fn build_landmarks(&self, count: usize, strategy: LandmarkStrategy) -> anyhow::Result<()> {
+        let component = self
+            .weakly_connected_components()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx, component)| (component == Some(0)).then_some(idx))
+            .collect::<Vec<_>>();
+        if component.is_empty() {
+            return Err(anyhow::anyhow!("graph has no nodes"));
+        }
+
+        let mut table = LandmarkTable {
+            graph_hash: self.graph_hash(),
+            landmarks: Vec::new(),
+            from_landmark: Vec::new(),
+            to_landmark: Vec::new(),
+        };
+
+        while table.landmarks.len() < count {
+            let root = component[rand::random::<usize>() % component.len()];
+            let next = if table.landmarks.is_empty() {
+                // start with the node farthest from a random root
+                let (distances, _) = self.shortest_path_tree(root, false);
+                farthest_node(&[distances])
+            } else {
+                match strategy {
+                    LandmarkStrategy::Farthest => farthest_node(&table.from_landmark),
+                    LandmarkStrategy::Avoid => self
+                        .avoid_node(root, &table)
+                        .or_else(|| farthest_node(&table.from_landmark)),
+                }
+            };
+            let Some(next) = next.filter(|next| !table.landmarks.contains(next)) else {
+                break;
+            };
+
+            let (from_distances, _) = self.shortest_path_tree(next, false);
+            let (to_distances, _) = self.shortest_path_tree(next, true);
+            table.landmarks.push(next);
+            table.from_landmark.push(from_distances);
+            table.to_landmark.push(to_distances);
+        }
+
+        *self.landmarks.as_ref().write().unwrap() = Some(table);
+        Ok(())
+    }
+
+    /// stores landmark tables built for this graph, e.g. from another graph with the same nodes and edges.
+    /// Graphs written with `to_mpk` keep their tables
+    pub fn set_landmarks(&self, table: LandmarkTable) -> anyhow::Result<()> {
+        if table.graph_hash != self.graph_hash() {
+            return Err(anyhow::anyhow!(
+                "landmark tables were built for a different graph"
+            ));
+        }
+        *self.landmarks.as_ref().write().unwrap() = Some(table);
+        Ok(())
+    }
+
+    /// Dijkstra search from a node index to all nodes, following the reverse adjacency if `reverse` is set.
+    /// Returns the distances and the parents of the shortest path tree.
+    fn shortest_path_tree(&self, source: usize, reverse: bool) -> (Vec<f64>, Vec<Option<usize>>) {
+        let nr_nodes = self.nodes.as_ref().read().unwrap().len();
+        let edges_access = self.edges.as_ref().read().unwrap();
+        let reverse_edges_access = self.reverse_edges.as_ref().read().unwrap();
+
+        let mut distances = vec![f64::INFINITY; nr_nodes];
+        let mut parents: Vec<Option<usize>> = vec![None; nr_nodes];
+        let mut q: BinaryHeap<Reverse<DijkstraNode>> = BinaryHeap::new();
+
+        distances[source] = 0.0;
+        q.push(Reverse(DijkstraNode {
+            id: source,
+            distance: 0.0,
+        }));
+
+        while let Some(Reverse(current)) = q.pop() {
+            if current.distance > distances[current.id] {
+                continue;
+            }
+            let neighbors: Vec<(usize, f64)> = if reverse {
+                reverse_edges_access
+                    .get(&current.id)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|from| {
+                        let edge = edges_access
+                            .get(from)?
+                            .get(&Edge::new(*from, current.id, None, None))?;
+                        Some((*from, edge.weight.unwrap_or(1.0)))
+                    })
+                    .collect()
+            } else {
+                edges_access
+                    .get(&current.id)
+                    .into_iter()
+                    .flatten()
+                    .map(|edge| (edge.to, edge.weight.unwrap_or(1.0)))
+                    .collect()
+            };
+            for (next_idx, weight) in neighbors {
+                let distance = current.distance + weight;
+                if distance < distances[next_idx] {
+                    distances[next_idx] = distance;
+                    parents[next_idx] = Some(current.id);
+                    q.push(Reverse(DijkstraNode {
+                        id: next_idx,
+                        distance,
+                    }));
+                }
+            }
+        }
+        (distances, parents)
+    }
+
+    /// the avoid strategy: grows a shortest path tree from a root and weighs every node by the gap between its
+    /// distance and the current landmark bound. The new landmark is the leaf reached by descending into the
+    /// heaviest subtree that does not contain a landmark yet.
+    fn avoid_node(&self, root: usize, table: &LandmarkTable) -> Option<usize> {
+        let (distances, parents) = self.shortest_path_tree(root, false);
+
+        // depth in the tree, so that children are always processed before their parents
+        let mut depth: Vec<Option<usize>> = vec![None; distances.len()];
+        depth[root] = Some(0);
+        for (idx, distance) in distances.iter().enumerate() {
+            if !distance.is_finite() {
+                continue;
+            }
+            let mut chain = vec![];
+            let mut current = idx;
+            while depth[current].is_none() {
+                chain.push(current);
+                current = parents[current]?;
+            }
+            let mut current_depth = depth[current]?;
+            for node in chain.into_iter().rev() {
+                current_depth += 1;
+                depth[node] = Some(current_depth);
+            }
+        }
+        let mut order = (0..distances.len())
+            .filter(|idx| depth[*idx].is_some())
+            .collect::<Vec<_>>();
+        order.sort_by_key(|idx| Reverse(depth[*idx]));
+
+        let landmark_set = table.landmarks.iter().cloned().collect::<HashSet<_>>();
+        let mut size = order
+            .iter()
+            .map(|idx| (*idx, distances[*idx] - table.lower_bound(root, *idx)))
+            .collect::<HashMap<_, _>>();
+        let mut blocked: HashSet<usize> = HashSet::new();
+        let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+
+        // accumulate the weights bottom up, subtrees containing a landmark are blocked
+        for idx in order.iter() {
+            if landmark_set.contains(idx) {
+                blocked.insert(*idx);
+            }
+            if let Some(parent) = parents[*idx] {
+                children.entry(parent).or_default().push(*idx);
+                if blocked.contains(idx) {
+                    blocked.insert(parent);
+                } else {
+                    let child_size = size[idx];
+                    *size.get_mut(&parent).unwrap() += child_size;
+                }
+            }
+        }
+
+        let mut current = root;
+        while let Some(next) = children.get(&current).and_then(|children| {
+            children
+                .iter()
+                .filter(|child| !blocked.contains(child))
+                .max_by(|a, b| size[a].partial_cmp(&size[b]).unwrap())
+        }) {
+            current = *next;
+        }
+        if landmark_set.contains(&current) {
+            None
+        } else {
+            Some(current)
+        }
+    }
+}
+
+/// `minuend - subtrahend`, lowered by far more than the rounding error of the distances, so that the bound
+/// never exceeds the distance found by a search
+fn round_down(minuend: f64, subtrahend: f64) -> f64 {
+    minuend - subtrahend - minuend.abs() * BOUND_SLACK
+}
+
+/// the node whose minimum distance to all landmarks is the largest, unreachable nodes are ignored
+fn farthest_node(from_landmark: &[Vec<f64>]) -> Option<usize> {
+    let nr_nodes = from_landmark.first()?.len();
+    (0..nr_nodes)
+        .into_par_iter()
+        .map(|idx| {
+            let distance = from_landmark
+                .iter()
+                .map(|distances| distances[idx])
+                .fold(f64::INFINITY, f64::min);
+            (idx, distance)
+        })
+        .filter(|(_, distance)| distance.is_finite())
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(b.0.cmp(&a.0)))
+        .map(|(idx, _)| idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hexagon_graph::h3cell::H3Cell;
+    use crate::test_graph::{astar_distance, cell, disk_network, scaled_disk_network};
+
+    fn node_idx(graph: &Graph<H3Cell>, node: H3Cell) -> usize {
+        *graph.node_map.read().unwrap().get_by_left(&node).unwrap()
+    }
+
+    #[test]
+    fn bounds_do_not_exceed_the_distances() {
+        for strategy in [LandmarkStrategy::Farthest, LandmarkStrategy::Avoid] {
+            let graph = disk_network();
+            graph.build_landmarks(4, strategy).unwrap();
+            let landmarks = graph.landmarks.read().unwrap();
+            let table = landmarks.as_ref().unwrap();
+            assert_eq!(table.landmarks.len(), 4);
+
+            let nodes = (0..19).map(|idx| cell(idx, -1)).collect::<Vec<_>>();
+            for from in nodes.iter() {
+                let distances = nodes
+                    .iter()
+                    .map(|to| astar_distance(&graph, *from, *to).unwrap())
+                    .collect::<Vec<_>>();
+                for (to, distance) in nodes.iter().zip(distances.iter()) {
+                    let bound = table.lower_bound(node_idx(&graph, *from), node_idx(&graph, *to));
+                    assert!(bound <= *distance, "{bound} > {distance}");
+                }
+
+                let targets = [3, 11, 17];
+                let bounds = table.target_bounds(
+                    &targets
+                        .iter()
+                        .map(|idx| node_idx(&graph, nodes[*idx]))
+                        .collect::<Vec<_>>(),
+                );
+                let nearest = targets
+                    .iter()
+                    .map(|idx| distances[*idx])
+                    .fold(f64::INFINITY, f64::min);
+                assert!(table.lower_bound_to_targets(node_idx(&graph, *from), &bounds) <= nearest);
+            }
+        }
+    }
+
+    #[test]
+    fn astar_keeps_the_distances() {
+        let plain = disk_network();
+        let graph = disk_network();
+        graph.build_landmarks(3, LandmarkStrategy::Avoid).unwrap();
+        for from in (0..19).map(|idx| cell(idx, -1)) {
+            for to in (0..19).map(|idx| cell(idx, -1)) {
+                assert_eq!(
+                    astar_distance(&graph, from, to),
+                    astar_distance(&plain, from, to)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tables_only_fit_the_graph_they_were_built_for() {
+        let mut built = disk_network();
+        built
+            .build_landmarks(2, LandmarkStrategy::Farthest)
+            .unwrap();
+        let table = built.landmarks.read().unwrap().clone().unwrap();
+
+        // same cells and edges, other weights
+        assert!(scaled_disk_network(2.0)
+            .set_landmarks(table.clone())
+            .is_err());
+        assert!(disk_network().set_landmarks(table).is_ok());
+
+        built
+            .build_and_add_egde(cell(0, -1), cell(18, -1), Some(0.1), None, None)
+            .unwrap();
+        assert!(built.landmarks.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn tables_are_saved_with_the_graph() {
+        let graph = disk_network();
+        graph
+            .build_landmarks(2, LandmarkStrategy::Farthest)
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("landmarks-{}.mpk", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.to_mpk(path).unwrap();
+        let loaded = Graph::<H3Cell>::from_mpk(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.graph_hash(), graph.graph_hash());
+        assert_eq!(
+            loaded.landmarks.read().unwrap().as_ref().unwrap().landmarks,
+            graph.landmarks.read().unwrap().as_ref().unwrap().landmarks
+        );
+    }
+}
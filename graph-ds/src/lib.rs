@@ -1,4 +1,11 @@
+pub mod bidirectional;
+pub mod components;
+pub mod constrained;
 pub mod hexagon_graph;
+pub mod k_shortest;
+pub mod landmarks;
+pub mod mpk;
+pub mod pareto;
 pub mod u64_graph;
 
 use std::cmp::{Ordering, Reverse};
@@ -12,6 +19,7 @@ use std::{
 };
 
 use bimap::{BiHashMap, BiMap};
+use landmarks::LandmarkTable;
 
 use rayon::prelude::*;
 
@@ -20,7 +28,19 @@ use rayon::prelude::*;
 pub struct Graph<T> {
     pub nodes: Arc<RwLock<Vec<Option<Node<T>>>>>,
     pub edges: Arc<RwLock<HashMap<usize, HashSet<Edge>, nohash::BuildNoHashHasher<usize>>>>,
+    /// reverse adjacency, maps a node index to the indices of all nodes with an edge into it
+    pub reverse_edges: Arc<
+        RwLock<
+            HashMap<
+                usize,
+                HashSet<usize, nohash::BuildNoHashHasher<usize>>,
+                nohash::BuildNoHashHasher<usize>,
+            >,
+        >,
+    >,
     pub node_map: Arc<RwLock<BiMap<T, usize>>>,
+    /// optional landmark distance tables for the ALT heuristic, see `build_landmarks`
+    pub landmarks: Arc<RwLock<Option<LandmarkTable>>>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -57,6 +77,14 @@ impl Hash for Edge {
     }
 }
 
+/// node types that carry layer information
+///
+/// the base layer (walking network) is `-1`, the bike layer `-2`, the driving layer `-3` and transit route layers
+/// are `>= 0`
+pub trait Layered {
+    fn layer(&self) -> i16;
+}
+
 impl Edge {
     pub fn new(from: usize, to: usize, weight: Option<f64>, capacity: Option<f64>) -> Self {
         Self {
@@ -67,6 +95,36 @@ impl Edge {
             capacity,
         }
     }
+
+    /// the weight of the edge, using the weight list if an index is given
+    pub fn weight_at(&self, weight_list_index: Option<usize>) -> f64 {
+        if let (Some(weight_list), Some(list_idx)) = (&self.weight_list, weight_list_index) {
+            weight_list[list_idx]
+        } else {
+            self.weight.unwrap_or(1.0)
+        }
+    }
+}
+
+/// queue entry of the Dijkstra searches, ordered by distance. Wrap it in `Reverse` for a min-heap
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DijkstraNode {
+    pub id: usize,
+    pub distance: f64,
+}
+
+impl Eq for DijkstraNode {}
+
+impl Ord for DijkstraNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.partial_cmp(&other.distance).unwrap()
+    }
+}
+
+impl PartialOrd for DijkstraNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
@@ -74,7 +132,9 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
         Self {
             nodes: Arc::new(RwLock::new(Vec::new())),
             edges: Arc::new(RwLock::new(HashMap::default())),
+            reverse_edges: Arc::new(RwLock::new(HashMap::default())),
             node_map: Arc::new(RwLock::new(BiMap::new())),
+            landmarks: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -202,6 +262,17 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
         } else {
             edges.entry(start_node_index).or_default().insert(new_edge);
         }
+
+        // new edges can shorten paths, which makes existing landmark bounds inadmissible
+        self.drop_landmarks();
+
+        let Ok( mut reverse_edges) = self.reverse_edges.as_ref().write() else {
+            return Err(anyhow::anyhow!("could not get write lock on reverse edges"));
+        };
+        reverse_edges
+            .entry(end_node_index)
+            .or_default()
+            .insert(start_node_index);
         Ok(())
     }
 
@@ -218,9 +289,20 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
         // add node to the node_map
         node_map.insert(node.id, node_idx);
         node_list.push(Some(node));
+        // the landmark tables have no distances for the new node
+        self.drop_landmarks();
         Ok(node_idx)
     }
 
+    /// removes the landmark tables, they no longer match the graph after it changed
+    fn drop_landmarks(&self) {
+        if let Ok(mut landmarks) = self.landmarks.as_ref().write() {
+            if landmarks.is_some() {
+                *landmarks = None;
+            }
+        }
+    }
+
     /// removes a directed edge from the graph
     pub fn remove_edge(&mut self, from: T, to: T) -> anyhow::Result<()> {
         let node_map = self.node_map.as_ref().read().unwrap();
@@ -238,6 +320,15 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
         edges.entry(*from).and_modify(|edges| {
             edges.retain(|edge| edge.to != *to);
         });
+        self.reverse_edges
+            .as_ref()
+            .write()
+            .unwrap()
+            .entry(*to)
+            .and_modify(|sources| {
+                sources.remove(from);
+            });
+        self.drop_landmarks();
 
         Ok(())
     }
@@ -297,15 +388,6 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
             None
         };
 
-        let mut end_distances: HashMap<T, f64> = HashMap::new();
-
-        let global_target_list = end_list.as_ref().map(|end_list| {
-            end_list
-                .iter()
-                .filter_map(|end| node_map_access.get_by_left(end))
-                .collect::<HashSet<_>>()
-        });
-
         explored[start_idx] = true;
         distances[start_idx] = Some(0.0);
 
@@ -323,12 +405,6 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
                 q.push_back((edge_length, edge));
             });
 
-        if let Some(target_list) = &global_target_list {
-            if target_list.contains(&start_idx) {
-                end_distances.insert(*start, 0.0);
-            }
-        }
-
         while !q.is_empty() {
             let (current_distance, current_egde) = q
                 .pop_front()
@@ -362,8 +438,16 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
             }
         }
 
-        if end_list.is_some() {
-            let distances = end_distances.into_values().map(Some).collect();
+        if let Some(end_list) = end_list {
+            // one entry per target in the order of `end_list`, targets outside the graph have no distance
+            let distances = end_list
+                .iter()
+                .map(|end| {
+                    node_map_access
+                        .get_by_left(end)
+                        .and_then(|idx| distances[*idx])
+                })
+                .collect();
             return Ok((None, distances));
         }
         if end.is_some() {
@@ -413,6 +497,9 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
     }
 
     /// calculates the shortest path between two nodes using the A* algorithm, returns the path and the distance
+    /// * `heuristic(node, target)` estimates the remaining distance from a node to the target,
+    ///   it has to be admissible for the result to be the shortest path
+    /// * with an `end_list`, the heuristic is not used
     #[allow(clippy::too_many_arguments)]
     pub fn astar(
         &self,
@@ -470,19 +557,49 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
 
         let mut infinity = infinity.unwrap_or(std::f64::INFINITY);
 
+        // targets keep their position in `end_list`, targets outside the graph have no index
         let target_idx_list = target_list
             .iter()
-            .filter_map(|end| node_map_access.get_by_left(end))
+            .map(|end| node_map_access.get_by_left(end).copied())
             .collect::<Vec<_>>();
 
-        let mut target_idx_set = target_idx_list.iter().cloned().collect::<HashSet<_>>();
+        let known_target_idx_list = target_idx_list
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut target_idx_set = known_target_idx_list
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
 
         let is_single_target = end.is_some();
 
+        // the heuristic estimates the remaining distance to the target, with multiple targets it is not used.
+        // If landmarks are available, the stronger of both lower bounds is taken.
+        let landmarks_access = self.landmarks.as_ref().read().unwrap();
+        let landmarks = landmarks_access.as_ref();
+        let target_bounds = landmarks.map(|table| table.target_bounds(&known_target_idx_list));
+        let h = |idx: usize| {
+            let estimate = if is_single_target {
+                node_list_access[idx]
+                    .as_ref()
+                    .map_or(0.0, |node| heuristic(&node.id, &target_list[0]))
+            } else {
+                0.0
+            };
+            match (landmarks, &target_bounds) {
+                (Some(table), Some(bounds)) if !known_target_idx_list.is_empty() => {
+                    estimate.max(table.lower_bound_to_targets(idx, bounds))
+                }
+                _ => estimate,
+            }
+        };
+
         g_score[start_idx] = Some(0.0);
         q.push(Reverse(AStarNode {
             id: start_idx,
-            f_score: heuristic(start, &target_list[0]),
+            f_score: h(start_idx),
         }));
 
         while !q.is_empty() {
@@ -513,7 +630,7 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
                         single_target: is_single_target,
                         distances: target_idx_list
                             .into_iter()
-                            .map(|idx| g_score[*idx])
+                            .map(|idx| idx.and_then(|idx| g_score[idx]))
                             .collect::<Vec<_>>(),
                     });
                 }
@@ -540,11 +657,7 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
                         g_score[next_edge_target_idx] = Some(tentative_g_score);
                         q.push(Reverse(AStarNode {
                             id: next_edge_target_idx,
-                            f_score: tentative_g_score
-                                + heuristic(
-                                    &node_list_access[next_edge_target_idx].as_ref().unwrap().id,
-                                    &node_list_access[current_idx].as_ref().unwrap().id,
-                                ),
+                            f_score: tentative_g_score + h(next_edge_target_idx),
                         }));
                     }
                 }
@@ -557,7 +670,7 @@ impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
                 single_target: is_single_target,
                 distances: target_idx_list
                     .into_iter()
-                    .map(|idx| g_score[*idx])
+                    .map(|idx| idx.and_then(|idx| g_score[idx]))
                     .collect::<Vec<_>>(),
             })
         } else {
@@ -616,6 +729,91 @@ impl<T: Eq + Hash + Copy + Send + Ord + Sync + std::fmt::Debug> Default for Grap
 fn graph_ds(_py: pyo3::Python, m: &pyo3::types::PyModule) -> pyo3::PyResult<()> {
     m.add_class::<hexagon_graph::PyH3Graph>()?;
     m.add_class::<hexagon_graph::PyCellGraph>()?;
+    m.add_class::<hexagon_graph::od_matrix::PyOdMatrix>()?;
+    m.add_function(pyo3::wrap_pyfunction!(
+        hexagon_graph::aggregate::py_aggregate_to_parents,
+        m
+    )?)?;
+    m.add_function(pyo3::wrap_pyfunction!(
+        hexagon_graph::aggregate::py_aggregate_to_districts,
+        m
+    )?)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod test_graph {
+    use h3o::{LatLng, Resolution};
+
+    use crate::{hexagon_graph::h3cell::H3Cell, Graph};
+
+    /// the `idx`-th of 19 neighboring cells, on the given layer
+    pub fn cell(idx: usize, layer: i16) -> H3Cell {
+        let center = LatLng::new(52.52, 13.405)
+            .unwrap()
+            .to_cell(Resolution::Twelve);
+        H3Cell {
+            cell: center.grid_disk::<Vec<_>>(2)[idx],
+            layer,
+        }
+    }
+
+    /// a graph with the given directed edges and weights
+    pub fn graph(edges: &[(H3Cell, H3Cell, f64)]) -> Graph<H3Cell> {
+        let mut graph = Graph::new();
+        for (from, to, weight) in edges {
+            graph
+                .build_and_add_egde(*from, *to, Some(*weight), None, None)
+                .unwrap();
+        }
+        graph
+    }
+
+    /// walking from 0 to 3 takes 30 minutes. Route 0 boards at 0 and reaches 2, routes 1 and 2 together take
+    /// 6 minutes with two boardings, walking to 1 and taking route 2 takes 13 minutes with one boarding
+    pub fn transit_network() -> Graph<H3Cell> {
+        let walk = |idx| cell(idx, -1);
+        graph(&[
+            (walk(0), walk(1), 10.0),
+            (walk(1), walk(2), 10.0),
+            (walk(2), walk(3), 10.0),
+            (walk(0), cell(0, 0), 2.0),
+            (cell(0, 0), cell(2, 0), 5.0),
+            (cell(2, 0), walk(2), 0.0),
+            (walk(0), cell(0, 1), 1.0),
+            (cell(0, 1), cell(1, 1), 2.0),
+            (cell(1, 1), walk(1), 0.0),
+            (walk(1), cell(1, 2), 1.0),
+            (cell(1, 2), cell(3, 2), 2.0),
+            (cell(3, 2), walk(3), 0.0),
+        ])
+    }
+
+    /// the 19 cells connected to their neighbors, the weights differ by direction
+    pub fn disk_network() -> Graph<H3Cell> {
+        scaled_disk_network(1.0)
+    }
+
+    /// the disk network with all weights multiplied by `factor`, the node indices are the same
+    pub fn scaled_disk_network(factor: f64) -> Graph<H3Cell> {
+        let mut edges = Vec::new();
+        for from in 0..19 {
+            for to in 0..19 {
+                if cell(from, -1).cell.grid_distance(cell(to, -1).cell) == Ok(1) {
+                    let weight = ((from * 7 + to * 3) % 5 + 1) as f64 * factor;
+                    edges.push((cell(from, -1), cell(to, -1), weight));
+                }
+            }
+        }
+        graph(&edges)
+    }
+
+    /// the distance found by the plain A* search
+    pub fn astar_distance(graph: &Graph<H3Cell>, start: H3Cell, end: H3Cell) -> Option<f64> {
+        graph
+            .astar(&start, Some(&end), None, None, None, None, |_, _| 0.0)
+            .ok()
+            .and_then(|res| res.distances[0])
+    }
+}
@@ -0,0 +1,109 @@
+use std::hash::Hash;
+use std::io::Write;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{landmarks::LandmarkTable, Graph, Node};
+
+/// a graph as stored in a brotli compressed MessagePack file
+///
+/// the nodes are kept in index order, so the landmark tables stay valid for the loaded graph
+#[derive(Serialize, Deserialize)]
+struct GraphMpk<T> {
+    nodes: Vec<Option<T>>,
+    /// from index, to index, weight, weight list and capacity
+    #[allow(clippy::type_complexity)]
+    edges: Vec<(usize, usize, Option<f64>, Option<Vec<f64>>, Option<f64>)>,
+    landmarks: Option<LandmarkTable>,
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug> Graph<T> {
+    /// writes the graph with its landmark tables to a brotli compressed MessagePack file
+    pub fn to_mpk(&self, path: &str) -> anyhow::Result<()>
+    where
+        T: Serialize,
+    {
+        let nodes = self
+            .nodes
+            .as_ref()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|node| node.as_ref().map(|node| node.id))
+            .collect();
+        let mut edges = self
+            .edges
+            .as_ref()
+            .read()
+            .unwrap()
+            .values()
+            .flatten()
+            .map(|edge| {
+                (
+                    edge.from,
+                    edge.to,
+                    edge.weight,
+                    edge.weight_list.clone(),
+                    edge.capacity,
+                )
+            })
+            .collect::<Vec<_>>();
+        edges.sort_by_key(|(from, to, ..)| (*from, *to));
+        let graph = GraphMpk {
+            nodes,
+            edges,
+            landmarks: self.landmarks.as_ref().read().unwrap().clone(),
+        };
+
+        let file = std::fs::File::create(path)?;
+        let mut brotli_writer = brotli::CompressorWriter::new(file, 4096, 9, 22);
+        brotli_writer.write_all(&rmp_serde::to_vec(&graph)?)?;
+        brotli_writer.flush()?;
+        Ok(())
+    }
+
+    /// reads a graph written by `to_mpk`, the landmark tables are checked against the loaded graph
+    pub fn from_mpk(path: &str) -> anyhow::Result<Self>
+    where
+        T: DeserializeOwned,
+    {
+        let file = std::fs::File::open(path)?;
+        let brotli_reader = brotli::Decompressor::new(file, 4096);
+        let stored: GraphMpk<T> = rmp_serde::from_read(brotli_reader)?;
+
+        let mut graph = Graph::<T>::new();
+        {
+            let mut node_list = graph.nodes.as_ref().write().unwrap();
+            let mut node_map = graph.node_map.as_ref().write().unwrap();
+            for id in stored.nodes.iter() {
+                match id {
+                    Some(id) => {
+                        graph.add_node(
+                            Node {
+                                id: *id,
+                                layer: None,
+                            },
+                            &mut node_list,
+                            &mut node_map,
+                        )?;
+                    }
+                    // keeps the indices of the remaining nodes
+                    None => node_list.push(None),
+                }
+            }
+        }
+        for (from, to, weight, weight_list, capacity) in stored.edges {
+            let (Some(Some(from)), Some(Some(to))) = (stored.nodes.get(from), stored.nodes.get(to))
+            else {
+                return Err(anyhow::anyhow!(
+                    "edge {from} -> {to} has no nodes in {path}"
+                ));
+            };
+            graph.build_and_add_egde(*from, *to, weight, weight_list, capacity)?;
+        }
+        if let Some(table) = stored.landmarks {
+            graph.set_landmarks(table)?;
+        }
+        Ok(graph)
+    }
+}
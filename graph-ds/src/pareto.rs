@@ -0,0 +1,235 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+use crate::constrained::{Criteria, EdgeKind, Label, LabelBags, LabelEntry, RoutingConstraints};
+use crate::{Graph, Layered};
+
+/// a journey of a Pareto set, no other journey of the set is at least as good in all criteria
+#[derive(Debug, Clone)]
+pub struct Journey<T> {
+    pub path: Vec<T>,
+    pub time: f64,
+    pub transfers: u32,
+    pub walk_time: f64,
+    pub walk_distance: f64,
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync + Ord + std::fmt::Debug + Layered> Graph<T> {
+    /// multi-label Dijkstra search that returns the Pareto set of journeys between two nodes,
+    /// trading off the total travel time, the number of transfers and the walking time
+    ///
+    /// * the walking distance is compared as well if `constraints` limit it
+    /// * labels that are dominated by a journey already found at the target are discarded,
+    ///   the heuristic has to be admissible for this to be exact. Landmark bounds are used next to it if available
+    /// * the journeys are sorted by travel time
+    #[allow(clippy::too_many_arguments)]
+    pub fn pareto_journeys(
+        &self,
+        start: &T,
+        end: &T,
+        infinity: Option<f64>,
+        weight_list_index: Option<usize>,
+        constraints: &RoutingConstraints,
+        heuristic: impl Fn(&T, &T) -> f64,
+        walk_distance: impl Fn(&T, &T) -> f64,
+    ) -> anyhow::Result<Vec<Journey<T>>> {
+        let node_map_access = self.node_map.as_ref().read().unwrap();
+        let node_list_access = self.nodes.as_ref().read().unwrap();
+        let edges_access = self.edges.as_ref().read().unwrap();
+
+        let start_idx = *node_map_access
+            .get_by_left(start)
+            .ok_or_else(|| anyhow::anyhow!("start node {start:?} not found in node map"))?;
+        let end_idx = *node_map_access
+            .get_by_left(end)
+            .ok_or_else(|| anyhow::anyhow!("end node {end:?} not found in node map"))?;
+
+        let infinity = infinity.unwrap_or(f64::INFINITY);
+        let node_id = |idx: usize| node_list_access[idx].as_ref().map(|node| node.id);
+        let landmarks_access = self.landmarks.as_ref().read().unwrap();
+        let landmarks = landmarks_access.as_ref();
+        let h = |idx: usize| {
+            let estimate = node_id(idx).map_or(0.0, |id| heuristic(&id, end));
+            landmarks.map_or(estimate, |table| {
+                estimate.max(table.lower_bound(idx, end_idx))
+            })
+        };
+
+        let criteria = Criteria {
+            transfers: true,
+            walk_time: true,
+            ..Criteria::from(constraints)
+        };
+        let mut bags = LabelBags::new(node_list_access.len());
+        let mut q: BinaryHeap<Reverse<LabelEntry>> = BinaryHeap::new();
+        let mut target_labels: Vec<Label> = Vec::new();
+        let mut journeys: Vec<Journey<T>> = Vec::new();
+
+        let start_label = Label {
+            node: start_idx,
+            time: 0.0,
+            transfers: 0,
+            walk_time: 0.0,
+            walk_distance: 0.0,
+            parent: None,
+        };
+        if let Some(label) = bags.insert(start_label, &criteria) {
+            q.push(Reverse(LabelEntry {
+                label,
+                f_score: h(start_idx),
+            }));
+        }
+
+        while let Some(Reverse(entry)) = q.pop() {
+            if bags.is_dead(entry.label) {
+                continue;
+            }
+            let current = bags.labels[entry.label];
+
+            // target pruning, the optimistic estimate of the label is compared against the journeys found so far
+            let estimate = Label {
+                time: entry.f_score,
+                ..current
+            };
+            if target_labels
+                .iter()
+                .any(|label| label.dominates(&estimate, &criteria))
+            {
+                continue;
+            }
+
+            if current.node == end_idx {
+                // extending a label beyond the target can not improve any criterion
+                target_labels.push(current);
+                journeys.push(Journey {
+                    path: bags
+                        .backtrace(entry.label)
+                        .into_iter()
+                        .filter_map(node_id)
+                        .collect(),
+                    time: current.time,
+                    transfers: current.transfers,
+                    walk_time: current.walk_time,
+                    walk_distance: current.walk_distance,
+                });
+                continue;
+            }
+
+            let Some(next_edges) = edges_access.get(&current.node) else {
+                continue;
+            };
+            let Some(current_id) = node_id(current.node) else {
+                continue;
+            };
+            for next_edge in next_edges.iter() {
+                let Some(next_id) = node_id(next_edge.to) else {
+                    continue;
+                };
+                let weight = next_edge.weight_at(weight_list_index);
+                let kind = EdgeKind::between(&current_id, &next_id);
+                let distance = if kind == EdgeKind::Walk && criteria.walk_distance {
+                    walk_distance(&current_id, &next_id)
+                } else {
+                    0.0
+                };
+
+                let next = current.extend(entry.label, next_edge.to, weight, kind, distance);
+                if next.time > infinity || !next.is_feasible(constraints) {
+                    continue;
+                }
+                if let Some(label) = bags.insert(next, &criteria) {
+                    q.push(Reverse(LabelEntry {
+                        label,
+                        f_score: next.time + h(next.node),
+                    }));
+                }
+            }
+        }
+
+        // journeys with equal travel times can arrive in any order, remove the dominated ones
+        let journeys = journeys
+            .into_iter()
+            .zip(target_labels.iter())
+            .filter(|(_, label)| {
+                !target_labels.iter().any(|other| {
+                    other.dominates(label, &criteria) && !label.dominates(other, &criteria)
+                })
+            })
+            .map(|(journey, _)| journey)
+            .collect::<Vec<_>>();
+
+        if journeys.is_empty() {
+            Err(anyhow::anyhow!("no path found"))
+        } else {
+            Ok(journeys)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hexagon_graph::h3cell::H3Cell;
+    use crate::test_graph::{astar_distance, cell, transit_network};
+
+    fn journeys(graph: &Graph<H3Cell>, constraints: RoutingConstraints) -> Vec<Journey<H3Cell>> {
+        graph
+            .pareto_journeys(
+                &cell(0, -1),
+                &cell(3, -1),
+                None,
+                None,
+                &constraints,
+                |_, _| 0.0,
+                H3Cell::distance_m,
+            )
+            .unwrap()
+    }
+
+    fn criteria(journeys: &[Journey<H3Cell>]) -> Vec<(f64, u32, f64)> {
+        journeys
+            .iter()
+            .map(|journey| (journey.time, journey.transfers, journey.walk_time))
+            .collect()
+    }
+
+    #[test]
+    fn returns_the_pareto_set() {
+        let graph = transit_network();
+        let journeys = journeys(&graph, RoutingConstraints::default());
+        assert_eq!(
+            criteria(&journeys),
+            vec![(6.0, 2, 0.0), (13.0, 1, 10.0), (30.0, 0, 30.0)]
+        );
+        assert_eq!(
+            Some(journeys[0].time),
+            astar_distance(&graph, cell(0, -1), cell(3, -1))
+        );
+        assert_eq!(
+            journeys[2].path,
+            (0..4).map(|idx| cell(idx, -1)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn no_journey_dominates_another() {
+        let journeys = journeys(&transit_network(), RoutingConstraints::default());
+        for (i, a) in journeys.iter().enumerate() {
+            for b in journeys.iter().skip(i + 1) {
+                assert!(a.time < b.time, "the journeys are sorted by travel time");
+                assert!(a.transfers > b.transfers || a.walk_time > b.walk_time);
+            }
+        }
+    }
+
+    #[test]
+    fn respects_the_constraints() {
+        let constraints = RoutingConstraints {
+            max_transfers: Some(1),
+            ..Default::default()
+        };
+        let journeys = journeys(&transit_network(), constraints);
+        assert_eq!(criteria(&journeys), vec![(13.0, 1, 10.0), (30.0, 0, 30.0)]);
+    }
+}